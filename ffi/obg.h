@@ -0,0 +1,55 @@
+/*
+ * C interface of obg, built with `make ffi` into target/debug/libobg.so
+ *
+ * Ownership:
+ *   - keys returned through `out` belong to the caller, release them with
+ *     obg_key_free()
+ *   - buffers returned through `out`/`out_len` belong to the caller,
+ *     release them with obg_buffer_free() passing the same length, never
+ *     with free()
+ *   - buffers and strings passed in remain owned by the caller and are
+ *     not retained past the call
+ *   - the string returned by obg_last_error() belongs to obg
+ *
+ * Every function returning int returns 0 on success or else the exit code
+ * the obg command-line exits with on the same error.
+ */
+#ifndef OBG_H
+#define OBG_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ObgKey obg_key_t;
+
+/* message of the last failure on the calling thread or NULL, valid until
+ * the next failing call on the same thread */
+const char *obg_last_error(void);
+
+/* loads a yaml or binary key file as `--key-file` does */
+int obg_key_load(const char *path, obg_key_t **out);
+/* parses the contents of a yaml key file */
+int obg_key_from_yaml(const char *yaml, obg_key_t **out);
+/* NULL is ignored */
+void obg_key_free(obg_key_t *key);
+
+/* output is exactly what `obg encrypt file` writes */
+int obg_encrypt_buffer(const obg_key_t *key, const uint8_t *data, size_t len,
+                       uint8_t **out, size_t *out_len);
+int obg_decrypt_buffer(const obg_key_t *key, const uint8_t *data, size_t len,
+                       uint8_t **out, size_t *out_len);
+/* NULL is ignored */
+void obg_buffer_free(uint8_t *data, size_t len);
+
+int obg_encrypt_file(const obg_key_t *key, const char *input, const char *output);
+int obg_decrypt_file(const obg_key_t *key, const char *input, const char *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OBG_H */
@@ -0,0 +1,108 @@
+/* round-trips through the C interface, run with `make ffi` */
+#include "obg.h"
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define CHECK(call)                                                          \
+    do {                                                                     \
+        if ((call) != 0) {                                                   \
+            fprintf(stderr, "%s:%d %s: %s\n", __FILE__, __LINE__, #call,     \
+                    obg_last_error());                                       \
+            exit(1);                                                         \
+        }                                                                    \
+    } while (0)
+
+static char *slurp(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        perror(path);
+        exit(1);
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    rewind(file);
+    char *bytes = malloc(*len + 1);
+    if (fread(bytes, 1, *len, file) != *len) {
+        perror(path);
+        exit(1);
+    }
+    bytes[*len] = '\0';
+    fclose(file);
+    return bytes;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s <yaml-key-file> <workdir>\n", argv[0]);
+        return 2;
+    }
+    const char *plaintext = "Hello World from C";
+    obg_key_t *key = NULL;
+    CHECK(obg_key_load(argv[1], &key));
+
+    uint8_t *ciphertext = NULL;
+    size_t ciphertext_len = 0;
+    CHECK(obg_encrypt_buffer(key, (const uint8_t *)plaintext, strlen(plaintext),
+                             &ciphertext, &ciphertext_len));
+
+    uint8_t *decrypted = NULL;
+    size_t decrypted_len = 0;
+    CHECK(obg_decrypt_buffer(key, ciphertext, ciphertext_len, &decrypted,
+                             &decrypted_len));
+    if (decrypted_len != strlen(plaintext) ||
+        memcmp(decrypted, plaintext, decrypted_len) != 0) {
+        fprintf(stderr, "buffer round-trip mismatch\n");
+        return 1;
+    }
+    obg_buffer_free(decrypted, decrypted_len);
+
+    /* decrypting plaintext fails with 0x54 like `obg decrypt` */
+    if (obg_decrypt_buffer(key, (const uint8_t *)plaintext, strlen(plaintext),
+                           &decrypted, &decrypted_len) != 0x54 ||
+        obg_last_error() == NULL) {
+        fprintf(stderr, "decrypting plaintext did not fail\n");
+        return 1;
+    }
+
+    /* a key parsed from yaml decrypts what the loaded key encrypted */
+    size_t yaml_len = 0;
+    char *yaml = slurp(argv[1], &yaml_len);
+    obg_key_t *parsed = NULL;
+    CHECK(obg_key_from_yaml(yaml, &parsed));
+    free(yaml);
+
+    char buffer[4096], output[4096], encrypted[4096], final[4096];
+    snprintf(buffer, sizeof buffer, "%s/buffer.obg", argv[2]);
+    snprintf(output, sizeof output, "%s/decrypted.txt", argv[2]);
+    snprintf(encrypted, sizeof encrypted, "%s/encrypted.obg", argv[2]);
+    snprintf(final, sizeof final, "%s/roundtrip.txt", argv[2]);
+    FILE *file = fopen(buffer, "wb");
+    fwrite(ciphertext, 1, ciphertext_len, file);
+    fclose(file);
+    obg_buffer_free(ciphertext, ciphertext_len);
+
+    CHECK(obg_decrypt_file(parsed, buffer, output));
+    size_t output_len = 0;
+    char *roundtrip = slurp(output, &output_len);
+    if (strcmp(roundtrip, plaintext) != 0) {
+        fprintf(stderr, "file decryption mismatch\n");
+        return 1;
+    }
+    CHECK(obg_encrypt_file(parsed, output, encrypted));
+    CHECK(obg_decrypt_file(key, encrypted, final));
+    free(roundtrip);
+    roundtrip = slurp(final, &output_len);
+    if (strcmp(roundtrip, plaintext) != 0) {
+        fprintf(stderr, "file round-trip mismatch\n");
+        return 1;
+    }
+    free(roundtrip);
+
+    obg_key_free(parsed);
+    obg_key_free(key);
+    obg_key_free(NULL);
+    printf("ffi ok\n");
+    return 0;
+}
@@ -20,16 +20,27 @@ pub use crate::aescbc::kd::pbkdf2_sha512;
 pub use crate::aescbc::kd::pbkdf2_sha512_256bits;
 pub use crate::aescbc::kd::pbkdf2_sha256;
 pub use crate::aescbc::kd::DerivationScheme;
+pub use crate::aescbc::descriptor::OpaqueDescriptor;
 pub use crate::aescbc::pad::Ansix923;
 pub use crate::aescbc::pad::Padder128;
 pub use crate::aescbc::pad::Padding;
+pub use crate::aescbc::config::{IvDerivation, Pbkdf2Config};
+pub use crate::aescbc::pv::{KeyOrigin, Provenance, SourceRecord};
+pub use crate::aescbc::secret::{SecretArray, SecretBytes};
 pub use crate::aescbc::tp::{B128, B256};
-pub use crate::errors::Error;
+pub use crate::errors::{DecryptionError, Error};
+use crate::errors::FileSystemError;
 pub use crate::hashis::gcrc128;
 pub use crate::hashis::gcrc256;
+use crate::aescbc::xor::xor;
 pub use crate::ioutils::{absolute_path, open_write, read_bytes, read_bytes_high_water_mark};
+use crate::serial::{self, FileFormat};
+use crate::sources::SecretSource;
 use hex;
 use rand::prelude::*;
+use rand::rngs::OsRng;
+use sha3::{Digest, Sha3_256};
+use zeroize::{Zeroize, Zeroizing};
 
 use aes::cipher::{
     // generic_array::{GenericArray, ArrayLength, typenum::U8};
@@ -49,34 +60,19 @@ pub trait EncryptionEngine {
     fn decrypt_blocks(&self, ciphertext: &[u8]) -> Vec<u8>;
 }
 
-pub fn xor_128(left: B128, right: B128) -> B128 {
-    let mut result: B128 = [0; 16];
-    for (i, (s, o)) in left.into_iter().zip(right.iter()).enumerate() {
-        result[i] = s ^ o;
-    }
-    result
-}
-
-pub fn xor_256(left: B256, right: B256) -> B256 {
-    let mut result: B256 = [0; 32];
-    for (i, (s, o)) in left.into_iter().zip(right.iter()).enumerate() {
-        result[i] = s ^ o;
-    }
-    result
-}
-
-pub fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
-    a.into_iter().zip(b.iter()).map(|(a, b)| a ^ b).collect()
-}
-
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub struct Aes256Key {
     pub version: String,
     pub cycles: Option<u64>,
-    pub key: String,
-    pub iv: String,
-    pub blob: String,
+    #[serde(with = "crate::aescbc::secret::hex")]
+    pub key: SecretBytes,
+    #[serde(with = "crate::aescbc::secret::hex")]
+    pub iv: SecretBytes,
+    #[serde(with = "crate::aescbc::secret::hex")]
+    pub blob: SecretBytes,
     name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
 }
 
 // MaGic PreFix
@@ -87,41 +83,117 @@ pub const VRSBUF: [u8; 12] = [
     0x00, 0x00, 0x00, 0b00000001, // 1
 ];
 pub const BLOBMINL: u64 = 237;
+/// exclusive upper bound of the blob lengths picked when none is given
+pub const BLOBMAXL: u64 = 1283;
+pub const DETERMINISTIC_CONTEXT: &[u8] = b"obg deterministic derivation";
+pub const IV_CONTEXT: &[u8] = b"obg iv derivation";
+/// iv of every key derived with [`IvDerivation::Legacy`]: the blob is
+/// at least [`BLOBMINL`] bytes of filler long before its checksums
+pub const LEGACY_IV: B128 = [0x0a; 16];
+/// magic prefix, name terminator, blob length, version, markers, cycles, key and iv
+pub const BINARY_KEY_MIN_LENGTH: usize = 8 + 4 + 8 + 12 + 8 + 4 + 8 + 32 + 16;
 pub const MK1: [u8; 8] = [0x01, 0x05, 0x16, 0x10, 0x50, 0x11, 0x0a, 0x12];
 pub const MK0: [u8; 4] = [0x26, 0x7b, 0xfe, 0x0e];
 
+/// fails unless `ciphertext` is made of whole aes blocks
+pub fn check_ciphertext(ciphertext: &[u8]) -> Result<(), Error> {
+    if !ciphertext.len().is_multiple_of(16) {
+        return Err(Error::DecryptionError(DecryptionError::new(format!(
+            "ciphertext is {} bytes long which is not a multiple of the block size",
+            ciphertext.len()
+        ))));
+    }
+    Ok(())
+}
+
 fn getcurrentversion() -> String {
     format!("obg-v{}", env!("CARGO_PKG_VERSION"))
 }
 
+/// name of a key saved as `path`
+pub fn key_name(path: &str) -> String {
+    match Path::new(path).file_name() {
+        Some(filename) => format!("{}", filename.to_string_lossy()),
+        None => path.to_string(),
+    }
+}
+
+/// SHA3-256 of the derived key and of the checksums the blob is made
+/// of, which stands in for the rng in deterministic derivations: its
+/// first 8 bytes pick the blob length and the next 15 shuffle the iv
+pub fn deterministic_seed(key: &B256, password: &[u8], salt: &[u8]) -> SecretArray<32> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(DETERMINISTIC_CONTEXT);
+    hasher.update(key);
+    hasher.update(gcrc256(password));
+    hasher.update(gcrc128(salt));
+    let mut seed = SecretArray::<32>::new([0u8; 32]);
+    seed.copy_from_slice(&hasher.finalize());
+    seed
+}
+
+/// iv of [`IvDerivation::Digest`]: SHA3-256 of the derived key and of
+/// the checksums the blob is made of, whose first 16 bytes are kept
+fn digest_iv(key: &B256, password: &[u8], salt: &[u8]) -> SecretArray<16> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(IV_CONTEXT);
+    hasher.update(key);
+    hasher.update(gcrc256(password));
+    hasher.update(gcrc128(salt));
+    let mut iv = SecretArray::<16>::new([0u8; 16]);
+    iv.copy_from_slice(&hasher.finalize()[..16]);
+    iv
+}
+
+/// Fisher-Yates shuffle driven by `seed` rather than by an rng whose
+/// algorithm may change across versions of rand
+fn shuffle_with_seed(bytes: &mut [u8], seed: &[u8]) {
+    for (n, i) in (1..bytes.len()).rev().enumerate() {
+        bytes.swap(i, seed[n] as usize % (i + 1));
+    }
+}
+
 pub fn match_prefix(magicpfx: &[u8]) -> bool {
     magicpfx.to_vec() == AESMGPF.to_vec()
 }
 // signs https://github.com/openbsd/src/blob/1835b44f319c9f17642bb957cc6602d2762cc3ae/sys/sys/signal.h#L51-L99
 
 impl Aes256Key {
-    pub fn skey(&self) -> B256 {
-        let mut skey: B256 = [0x0; 32];
-        skey.copy_from_slice(&hex::decode(self.key.as_bytes()).expect(&format!("{} key appears to be an invalid hex", match &self.name {
-            Some(name) => format!("{:?} ", name),
-            None => format!("")
-        }))[..32]);
-        skey
-    }
-    pub fn siv(&self) -> B128 {
-        let mut siv: B128 = [0x0; 16];
-        siv.copy_from_slice(&hex::decode(self.iv.as_bytes()).expect(&format!("{} iv appears to be an invalid hex", match &self.name {
-            Some(name) => format!("{:?} ", name),
-            None => format!("")
-        }))[..16]);
-        siv
-    }
-    pub fn sblob(&self) -> Vec<u8> {
-        match hex::decode(&self.blob) {
-            Ok(blob) => blob.clone(),
-            Err(e) => format!("{}", e).as_bytes().to_vec()
+    fn leading<'a, const N: usize>(&self, label: &str, secret: &'a SecretBytes) -> Result<&'a [u8; N], Error> {
+        match secret.leading::<N>() {
+            Some(bytes) => Ok(bytes),
+            None => Err(Error::InvalidKeyHex(format!(
+                "{} is {} bytes long instead of {}",
+                match &self.name {
+                    Some(name) => format!("{} of key {:?}", label, name),
+                    None => label.to_string(),
+                },
+                secret.len(),
+                N
+            ))),
         }
     }
+    pub fn skey(&self) -> Result<&B256, Error> {
+        self.leading("key", &self.key)
+    }
+    pub fn siv(&self) -> Result<&B128, Error> {
+        self.leading("iv", &self.iv)
+    }
+    pub fn sblob(&self) -> &[u8] {
+        self.blob.expose()
+    }
+    /// hex-encoded SHA3-256 digest of key, iv and blob which
+    /// identifies the key without revealing it
+    pub fn fingerprint(&self) -> Result<String, Error> {
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.skey()?);
+        hasher.update(self.siv()?);
+        hasher.update(self.sblob());
+        Ok(hex::encode(hasher.finalize()))
+    }
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
 
     pub fn with_name(&self, name: String) -> Result<Aes256Key, Error> {
         match &self.name {
@@ -133,96 +205,230 @@ impl Aes256Key {
                 blob: self.blob.clone(),
                 cycles: self.cycles.clone(),
                 version: self.version.clone(),
+                provenance: self.provenance.clone(),
             })
         }
     }
     pub fn new(key: B256, iv: B128, blob: &[u8], cycles: u64) -> Aes256Key {
+        let (key, iv) = (Zeroizing::new(key), Zeroizing::new(iv));
         Aes256Key {
             name: None,
-            key: hex::encode(key),
-            iv: hex::encode(iv),
-            blob: hex::encode(blob),
+            key: SecretBytes::from(&key[..]),
+            iv: SecretBytes::from(&iv[..]),
+            blob: SecretBytes::from(blob),
             cycles: Some(cycles),
             version: getcurrentversion(),
+            provenance: None,
         }
     }
+    /// draws key, iv and blob from the OS random number generator
+    /// in lieu of any derivation
+    pub fn random(blob_length: Option<u64>) -> Result<Aes256Key, Error> {
+        let len = match blob_length {
+            Some(bl) if bl < BLOBMINL => {
+                return Err(Error::NonValidKey(format!("blob length too small: {} (min {})", bl, BLOBMINL)));
+            }
+            Some(bl) => bl,
+            None => OsRng.gen_range(BLOBMINL..BLOBMAXL),
+        };
+        let mut key = vec![0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let mut iv = vec![0u8; 16];
+        OsRng.fill_bytes(&mut iv);
+        let mut blob = vec![0u8; len as usize];
+        OsRng.fill_bytes(&mut blob);
+        Ok(Aes256Key {
+            name: None,
+            key: SecretBytes::new(key),
+            iv: SecretBytes::new(iv),
+            blob: SecretBytes::new(blob),
+            cycles: None,
+            version: getcurrentversion(),
+            provenance: Some(Provenance::random()),
+        })
+    }
     pub fn derive_with_name(
         name: &str,
         passwords: Vec<String>,
-        password_hwm: u64,
         salts: Vec<String>,
-        salt_hwm: u64,
-        cycles: u64,
-        salt_derivation_scheme: DerivationScheme,
-        shuffle_iv: bool,
+        derivation: Pbkdf2Config,
         blob_length: Option<u64>,
     ) -> Result<Aes256Key, Error> {
-        let name = match Path::new(name).file_name() {
-            Some(filename) => format!("{}", filename.to_string_lossy()),
-            None => name.to_string(),
-        };
-
+        let name = key_name(name);
 
-        Self::derive(
-            passwords,
-            password_hwm,
-            salts,
-            salt_hwm,
-            cycles,
-            salt_derivation_scheme,
-            shuffle_iv,
-            blob_length,
-        )?.with_name(name.to_string())
+        Self::derive(passwords, salts, derivation, blob_length)?.with_name(name.to_string())
     }
+    /// derives a key out of the `passwords` and `salts` sources with the
+    /// scheme, cycles, iv shuffling and high-water marks of `derivation`,
+    /// sources are read whole when it has no high-water marks. The
+    /// sources are recorded in the provenance of the key.
     pub fn derive(
         passwords: Vec<String>,
-        password_hwm: u64,
         salts: Vec<String>,
-        salt_hwm: u64,
-        cycles: u64,
-        salt_derivation_scheme: DerivationScheme,
-        shuffle_iv: bool,
+        derivation: Pbkdf2Config,
         blob_length: Option<u64>,
     ) -> Result<Aes256Key, Error> {
-        let mut password = Vec::<u8>::new();
-        for sec in passwords {
-            password.extend(if Path::new(&sec).exists() {
-                read_bytes_high_water_mark(&sec, password_hwm)?
-            } else {
-                sec.as_str().as_bytes().to_vec()
-            });
+        Self::derive_from_sources(SecretSource::parse_all(&passwords)?, SecretSource::parse_all(&salts)?, derivation, blob_length)
+    }
+    /// same as [`Aes256Key::derive`] with sources already parsed, such
+    /// as secrets typed at a prompt which never pass through a string
+    pub fn derive_from_sources(
+        passwords: Vec<SecretSource>,
+        salts: Vec<SecretSource>,
+        derivation: Pbkdf2Config,
+        blob_length: Option<u64>,
+    ) -> Result<Aes256Key, Error> {
+        let password_hwm = derivation.password_hwm.unwrap_or(u64::MAX);
+        let salt_hwm = derivation.salt_hwm.unwrap_or(u64::MAX);
+        let mut password = Zeroizing::new(Vec::<u8>::new());
+        let mut password_records = Vec::<SourceRecord>::new();
+        for source in passwords {
+            let bytes = Zeroizing::new(source.read(password_hwm)?);
+            password_records.push(SourceRecord::password(&source));
+            password.extend(bytes.iter());
         }
 
-        let mut salt = Vec::<u8>::new();
-        for st in salts {
-            salt.extend(if Path::new(&st).exists() {
-                read_bytes_high_water_mark(&st, salt_hwm)?
-            } else {
-                st.as_str().as_bytes().to_vec()
-            });
+        let mut salt = Zeroizing::new(Vec::<u8>::new());
+        let mut salt_records = Vec::<SourceRecord>::new();
+        for source in salts {
+            let bytes = Zeroizing::new(source.read(salt_hwm)?);
+            salt_records.push(SourceRecord::salt(&source, &bytes));
+            salt.extend(bytes.iter());
+        }
+        let mut key = Self::derive_from_bytes(&password, &salt, &derivation, blob_length)?;
+        key.provenance = Some(Provenance::derived(Pbkdf2Config {
+            password_hwm: Some(password_hwm),
+            salt_hwm: Some(salt_hwm),
+            passwords: password_records,
+            salts: salt_records,
+            ..derivation
+        }));
+        Ok(key)
+    }
+    /// re-derives the key out of `passwords` and `salts` as recorded in
+    /// its provenance, or with `salt_derivation_scheme` and the cycles of
+    /// the key when it has none, and fails unless both keys are the same.
+    /// Keys without provenance may predate [`IvDerivation::Digest`] and
+    /// match with either iv.
+    pub fn verify_derivation(
+        &self,
+        passwords: Vec<SecretSource>,
+        password_hwm: u64,
+        salts: Vec<SecretSource>,
+        salt_hwm: u64,
+        salt_derivation_scheme: DerivationScheme,
+    ) -> Result<(), Error> {
+        let recorded = match &self.provenance {
+            Some(Provenance { origin: KeyOrigin::Random, .. }) => {
+                return Err(Error::KeyError(
+                    "key was drawn from the OS random number generator and cannot be re-derived".to_string(),
+                ));
+            }
+            Some(Provenance { derivation: Some(derivation), .. }) => derivation.clone(),
+            _ => match self.cycles {
+                Some(cycles) => Pbkdf2Config::new(salt_derivation_scheme, cycles, false, false),
+                None => return Err(Error::KeyError("key records no cycles to re-derive it with".to_string())),
+            },
+        };
+        let derived = Aes256Key::derive_from_sources(
+            passwords,
+            salts,
+            Pbkdf2Config::new(
+                recorded.scheme.clone(),
+                recorded.cycles,
+                recorded.shuffle_iv && recorded.deterministic,
+                recorded.deterministic,
+            )
+            .with_iv_derivation(recorded.iv_derivation)
+            .with_high_water_marks(
+                recorded.password_hwm.unwrap_or(password_hwm),
+                recorded.salt_hwm.unwrap_or(salt_hwm),
+            ),
+            Some(self.sblob().len() as u64),
+        )?;
+        if let Some(Provenance { derivation: Some(derivation), .. }) = &derived.provenance {
+            for (label, expected, given) in [
+                ("password", &recorded.passwords, &derivation.passwords),
+                ("salt", &recorded.salts, &derivation.salts),
+            ] {
+                if !expected.is_empty() && expected != given {
+                    return Err(Error::KeyError(format!(
+                        "{} sources differ from those recorded: {} instead of {}",
+                        label,
+                        given.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(", "),
+                        expected.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(", "),
+                    )));
+                }
+            }
+        }
+        // a shuffled iv is a permutation of the derived one, keys without
+        // provenance do not tell whether theirs was shuffled
+        let (mut iv, mut expected_iv) = (Zeroizing::new(*self.siv()?), Zeroizing::new(*derived.siv()?));
+        if (recorded.shuffle_iv && !recorded.deterministic) || self.provenance.is_none() {
+            iv.sort();
+            expected_iv.sort();
+        }
+        let legacy = self.provenance.is_none() && *iv == LEGACY_IV;
+        if self.skey()? != derived.skey()? || (iv != expected_iv && !legacy) || self.sblob() != derived.sblob() {
+            return Err(Error::KeyError(format!(
+                "key does not derive from the given password and salt with {} cycles of {}",
+                recorded.cycles, recorded.scheme
+            )));
         }
+        Ok(())
+    }
+    /// derives a key out of password and salt bytes already read from
+    /// their sources with the scheme, cycles, iv derivation and iv
+    /// shuffling of `derivation`, whose sources and high-water marks are
+    /// left to [`Aes256Key::derive_from_sources`]
+    ///
+    /// Unless `deterministic` the blob length, when not given, and the
+    /// shuffling of the iv are random, see [`deterministic_seed`].
+    pub fn derive_from_bytes(
+        password: &[u8],
+        salt: &[u8],
+        derivation: &Pbkdf2Config,
+        blob_length: Option<u64>,
+    ) -> Result<Aes256Key, Error> {
+        let (cycles, shuffle_iv, deterministic) = (derivation.cycles, derivation.shuffle_iv, derivation.deterministic);
         let mut rng = thread_rng();
-        let len = match blob_length {
-            Some(bl) => if bl < BLOBMINL {
+        if let Some(bl) = blob_length {
+            if bl < BLOBMINL {
                 return Err(Error::NonValidKey(format!("blob length too small: {} (min {})", bl, BLOBMINL)));
-            } else {
-                bl
-            },
-            None => {
-                let mut mods: Vec<u64> = (BLOBMINL..1283).collect();
+            }
+        }
+
+        let iterations = match u32::try_from(cycles) {
+            Ok(iterations) => iterations,
+            Err(_) => {
+                return Err(Error::KeyError(format!("{} cycles exceed the maximum of {}", cycles, u32::MAX)));
+            }
+        };
+        let mut key = Zeroizing::new([0xa; 32]);
+        key.copy_from_slice(&Zeroizing::new(derivation.scheme.derive(salt, password, iterations)));
+        let seed = match deterministic {
+            true => Some(deterministic_seed(&key, password, salt)),
+            false => None,
+        };
+        let len = match (blob_length, &seed) {
+            (Some(bl), _) => bl,
+            (None, Some(seed)) => {
+                let mut head = [0u8; 8];
+                head.copy_from_slice(&seed[..8]);
+                BLOBMINL + u64::from_be_bytes(head) % (BLOBMAXL - BLOBMINL)
+            }
+            (None, None) => {
+                let mut mods: Vec<u64> = (BLOBMINL..BLOBMAXL).collect();
                 mods.shuffle(&mut rng);
                 mods[0]
             }
         };
-
-        let mut key = [0xa; 32];
-        key.copy_from_slice(&salt_derivation_scheme.derive(&salt, &password, cycles as u32));
         let mut blob = Vec::<u8>::new();
         blob.resize(len as usize, 0xa);
         let mut tmp = Vec::<u8>::new();
-        tmp.extend(&gcrc256(&password));
+        tmp.extend(&gcrc256(password));
         tmp.reverse();
-        tmp.extend(&gcrc128(&salt));
+        tmp.extend(&gcrc128(salt));
         blob.extend(&gcrc256(&tmp[..37]));
         while blob.len() < len as usize + 16 {
             blob.extend(&gcrc256(&tmp[..37]));
@@ -230,11 +436,22 @@ impl Aes256Key {
         }
         let mut iv = [0; 16];
         iv.copy_from_slice(&blob.drain(..16).collect::<Vec<u8>>());
+        if derivation.iv_derivation == IvDerivation::Digest {
+            iv.copy_from_slice(&digest_iv(&key, password, salt)[..]);
+        }
         let blob = blob[..len as usize].to_vec();
         if shuffle_iv {
-            iv.shuffle(&mut rng);
+            match &seed {
+                Some(seed) => shuffle_with_seed(&mut iv, &seed[8..]),
+                None => iv.shuffle(&mut rng),
+            }
         }
-        Ok(Aes256Key::new(key, iv, &blob, cycles))
+        let mut key = Aes256Key::new(*key, iv, &blob, cycles);
+        key.provenance = Some(Provenance::derived(
+            Pbkdf2Config::new(derivation.scheme.clone(), cycles, shuffle_iv, deterministic)
+                .with_iv_derivation(derivation.iv_derivation),
+        ));
+        Ok(key)
     }
     pub fn load_from_file(
         filename: String,
@@ -244,88 +461,37 @@ impl Aes256Key {
         blob_offset: Option<usize>,
         moo: bool,
     ) -> Result<Aes256Key, Error> {
-        let mut bytes = read_bytes(&filename)?;
+        let bytes = read_bytes(&filename)?;
+        Self::from_carrier_bytes(bytes, strict, key_offset, salt_offset, blob_offset, moo)
+            .map_err(|error| match error {
+                Error::FileSystemError(error) => Error::FileSystemError(error.context(&filename)),
+                error => error,
+            })
+    }
+    /// carves a key out of the bytes of any file, the binary layout
+    /// when no offset is given
+    pub fn from_carrier_bytes(
+        bytes: Vec<u8>,
+        strict: bool,
+        key_offset: Option<usize>,
+        salt_offset: Option<usize>,
+        blob_offset: Option<usize>,
+        moo: bool,
+    ) -> Result<Aes256Key, Error> {
+        let mut bytes = Zeroizing::new(bytes);
         if key_offset== None && salt_offset == None && blob_offset == None && moo == false {
-            let mut lhs = bytes.len() - 16;
-            let siv: Vec<u8> = bytes.drain(lhs..).collect();
-
-            lhs -= 32;
-            let skey: Vec<u8> = bytes.drain(lhs..).collect();
-
-            lhs -= 8;
-            let cycles =
-                Some(match u64::from_str_radix(&hex::encode(bytes.drain(lhs..).collect::<Vec<u8>>()), 16) {
-                    Ok(c) => c,
-                    Err(e) => return Err(Error::NonValidKey(format!("invalid cycles: {}", e)))
-                });
-
-            lhs -= 4;
-            let mk0: Vec<u8> = bytes.drain(lhs..).collect();
-            if strict {assert_eq!(mk0, MK0);}
-
-            lhs -= 8;
-
-            let mk1: Vec<u8> = bytes.drain(lhs..).collect();
-            if strict {assert_eq!(mk1, MK1);}
-
-            lhs -= 12;
-
-            let vrsbuf: Vec<u8> = bytes.drain(lhs..).collect();
-            let version = format!(
-                "obg-v{}.{}.{}",
-                vrsbuf[3],
-                vrsbuf[7],
-                vrsbuf[11],
-            );
-            if strict {assert_eq!(vrsbuf, VRSBUF);}
-
-            lhs -=  8;
-            let len: usize =
-                u64::from_str_radix(&hex::encode(bytes.drain(lhs..).collect::<Vec<u8>>()), 16)? as usize;
-
-            let blob = if len > lhs {
-                if strict {
-                    return Err(Error::KeyError(format!("invalid blob length in binary key: {}", len)));
-                } else {
-                    bytes.clone()
+            Self::from_bytes(std::mem::take(&mut *bytes), strict)
+        } else {
+            let mut ml: usize = match (bytes.len() / 3).checked_sub(84) {
+                Some(ml) => ml,
+                None => {
+                    return Err(FileSystemError::new(format!(
+                        "{} bytes are too few for an opaque key",
+                        bytes.len()
+                    ))
+                    .into())
                 }
-            } else {
-                lhs -= len;
-                bytes.drain(lhs..).collect::<Vec<u8>>().to_vec()
             };
-
-            lhs = if lhs <=4 {
-                if strict {
-                    return Err(Error::KeyError(format!("invalid blob length in binary key: {}", len)));
-                } else {
-                    lhs
-                }
-            } else {
-                match moo {
-                    true => lhs / 2,
-                    false => lhs,
-                }
-            } - 4;
-
-            let ebytes = bytes.drain(lhs..).collect::<Vec<u8>>().to_vec();
-            if strict {assert_eq!(ebytes, [0x00, 0x00, 0x00, 0x00]);}
-            let aesmgpf =  bytes.drain(..8).collect::<Vec<u8>>().to_vec();
-            if strict {assert_eq!(aesmgpf, AESMGPF.to_vec());}
-            let fname = bytes.to_vec();
-
-            return Ok(Aes256Key {
-                key: hex::encode(skey),
-                iv: hex::encode(siv),
-                blob: hex::encode(blob),
-                version: version,
-                cycles: cycles,
-                name: match String::from_utf8(fname) {
-                    Ok(i) => Some(i),
-                    Err(_) => None
-                },
-            })
-        } else {
-            let mut ml: usize = (bytes.len() / 3) - 84;
             ml = match key_offset {
                 Some(o) => {
                     if o > ml {
@@ -361,10 +527,11 @@ impl Aes256Key {
                 false => bytes.len(),
             } < ml
             {
-                return Err(Error::FileSystemError(format!(
-                    "{} is too small for the set of constraints",
-                    filename
-                )));
+                return Err(FileSystemError::new(format!(
+                    "{} bytes are too few for the set of constraints",
+                    bytes.len()
+                ))
+                .into());
             }
 
             let mut lhs = (match moo {
@@ -391,31 +558,127 @@ impl Aes256Key {
             let blob = bytes.drain(lhs..).collect::<Vec<u8>>().to_vec();
 
             return Ok(Aes256Key {
-                key: hex::encode(skey),
-                iv: hex::encode(siv),
-                blob: hex::encode(blob),
+                key: SecretBytes::new(skey),
+                iv: SecretBytes::new(siv),
+                blob: SecretBytes::new(blob),
                 version: format!("confidential"),
                 cycles: Some(74),
                 name: None,
+                provenance: None,
             });
         }
     }
+    /// parses the binary layout of a key as written by [`Aes256Key::save_to_file`]
+    pub fn from_bytes(bytes: Vec<u8>, strict: bool) -> Result<Aes256Key, Error> {
+        let mut bytes = Zeroizing::new(bytes);
+        if bytes.len() < BINARY_KEY_MIN_LENGTH {
+            return Err(Error::NonValidKey(format!(
+                "binary key is {} bytes long, at least {} are required",
+                bytes.len(),
+                BINARY_KEY_MIN_LENGTH
+            )));
+        }
+        let mut lhs = bytes.len() - 16;
+        let siv: Vec<u8> = bytes.drain(lhs..).collect();
+
+        lhs -= 32;
+        let skey: Vec<u8> = bytes.drain(lhs..).collect();
+
+        lhs -= 8;
+        let cycles =
+            Some(match u64::from_str_radix(&hex::encode(bytes.drain(lhs..).collect::<Vec<u8>>()), 16) {
+                Ok(c) => c,
+                Err(e) => return Err(Error::NonValidKey(format!("invalid cycles: {}", e)))
+            });
+
+        lhs -= 4;
+        let mk0: Vec<u8> = bytes.drain(lhs..).collect();
+        if strict && mk0 != MK0 {
+            return Err(Error::NonValidKey("invalid MK0 marker in binary key".to_string()));
+        }
+
+        lhs -= 8;
+
+        let mk1: Vec<u8> = bytes.drain(lhs..).collect();
+        if strict && mk1 != MK1 {
+            return Err(Error::NonValidKey("invalid MK1 marker in binary key".to_string()));
+        }
+
+        lhs -= 12;
+
+        let vrsbuf: Vec<u8> = bytes.drain(lhs..).collect();
+        let version = format!(
+            "obg-v{}.{}.{}",
+            vrsbuf[3],
+            vrsbuf[7],
+            vrsbuf[11],
+        );
+        if strict && vrsbuf != VRSBUF {
+            return Err(Error::NonValidKey("unsupported version in binary key".to_string()));
+        }
+
+        lhs -=  8;
+        let len: usize =
+            u64::from_str_radix(&hex::encode(bytes.drain(lhs..).collect::<Vec<u8>>()), 16)? as usize;
+
+        let blob = if len > lhs {
+            if strict {
+                return Err(Error::KeyError(format!("invalid blob length in binary key: {}", len)));
+            } else {
+                bytes.to_vec()
+            }
+        } else {
+            lhs -= len;
+            bytes.drain(lhs..).collect::<Vec<u8>>().to_vec()
+        };
+
+        lhs = if lhs <=4 {
+            if strict {
+                return Err(Error::KeyError(format!("invalid blob length in binary key: {}", len)));
+            } else {
+                lhs
+            }
+        } else {
+            lhs
+        }
+        .saturating_sub(4);
+
+        let ebytes = bytes.drain(lhs..).collect::<Vec<u8>>().to_vec();
+        if strict && ebytes != [0x00, 0x00, 0x00, 0x00] {
+            return Err(Error::NonValidKey("invalid name terminator in binary key".to_string()));
+        }
+        let end = bytes.len().min(8);
+        let aesmgpf = bytes.drain(..end).collect::<Vec<u8>>().to_vec();
+        if strict && aesmgpf != AESMGPF.to_vec() {
+            return Err(Error::NonValidKey("invalid magic prefix in binary key".to_string()));
+        }
+        let fname = bytes.to_vec();
+
+        Ok(Aes256Key {
+            key: SecretBytes::new(skey),
+            iv: SecretBytes::new(siv),
+            blob: SecretBytes::new(blob),
+            version,
+            cycles,
+            name: String::from_utf8(fname).ok(),
+            provenance: None,
+        })
+    }
     pub fn save_to_file(&self, filename: String) -> Result<(), Error> {
+        let bytes = self.to_bytes(&filename)?;
         let mut file = open_write(&filename)?;
+        Ok(file.write_all(&bytes)?)
+    }
+    /// binary layout of the key as written by [`Aes256Key::save_to_file`]
+    pub fn to_bytes(&self, filename: &str) -> Result<Vec<u8>, Error> {
+        let mut file = Vec::<u8>::new();
         file.write_all(&AESMGPF.to_vec())?;
-        file.write_all(
-            match Path::new(&filename).file_name() {
-                Some(filename) => format!("{}", filename.to_string_lossy()),
-                None => filename.clone(),
-            }
-            .as_str()
-            .as_bytes(),
-        )?;
+        file.write_all(key_name(filename).as_bytes())?;
         file.write_all(&[0x00, 0x00, 0x00, 0x00])?;
-        let blob = hex::decode(&self.blob)?;
+        let blob = self.sblob();
         let len = blob.len();
 
-        file.write_all(&blob)?;
+        file.write_all(blob)?;
         file.write_all(&hex::decode(&format!("{:016x}", len))?)?;
 
         file.write_all(&VRSBUF)?;
@@ -428,9 +691,9 @@ impl Aes256Key {
                 None => 0,
             }
         ))?)?;
-        file.write_all(&self.skey())?;
-        file.write_all(&self.siv())?;
-        Ok(())
+        file.write_all(self.skey()?)?;
+        file.write_all(self.siv()?)?;
+        Ok(file)
     }
     pub fn save_to_yaml_file(&self, filename: String) -> Result<(), Error> {
         let mut file = open_write(&filename)?;
@@ -442,12 +705,37 @@ impl Aes256Key {
         let key: Aes256Key = serde_yaml::from_slice(&bytes)?;
         Ok(key)
     }
+    /// writes the key as yaml, json or toml
+    pub fn save_to_config_file(&self, filename: String, format: FileFormat) -> Result<(), Error> {
+        serial::save(self, &filename, format)?;
+        Ok(())
+    }
+    /// reads a yaml, json or toml key, see [`serial::load`]
+    pub fn load_from_config_file(filename: String) -> Result<Aes256Key, Error> {
+        serial::load(&filename)
+    }
+    /// loads a yaml, json or toml key or an [`OpaqueDescriptor`], files
+    /// which are not text or start with [`AESMGPF`] load as binary keys
+    pub fn load(filename: String) -> Result<Aes256Key, Error> {
+        let bytes = Zeroizing::new(read_bytes(&filename)?);
+        if match_prefix(bytes.get(..AESMGPF.len()).unwrap_or_default()) || std::str::from_utf8(&bytes).is_err() {
+            return Aes256Key::load_from_file(filename, false, None, None, None, false);
+        }
+        match Aes256Key::load_from_config_file(filename.clone()) {
+            Ok(key) => Ok(key),
+            Err(error) => match OpaqueDescriptor::load_from_config_file(filename) {
+                Ok(descriptor) => descriptor.load_key(),
+                Err(_) => Err(error),
+            },
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// the key only lives in the expanded round keys of `cipher`, which
+/// `aes` wipes on drop
+#[derive(Clone)]
 pub struct Aes256CbcCodec {
     cipher: Aes256,
-    key: B256,
     iv: B128,
     padding: Padding,
 }
@@ -457,31 +745,50 @@ impl Aes256CbcCodec {
         let padding = Padding::Ansix923(Ansix923::new(0xff as u8));
         Aes256CbcCodec::new_with_padding(key, iv, padding)
     }
-    pub fn new_with_padding(key: B256, iv: B128, padding: Padding) -> Aes256CbcCodec {
-        let gkey = GenericArray::from(key);
+    pub fn new_with_padding(mut key: B256, iv: B128, padding: Padding) -> Aes256CbcCodec {
+        let cipher = Aes256::new(GenericArray::from_slice(&key));
+        key.zeroize();
         Aes256CbcCodec {
-            cipher: Aes256::new(&gkey),
-            key: key,
-            iv: iv,
-            padding: padding,
+            cipher,
+            iv,
+            padding,
         }
     }
-    pub fn new_with_key(key: Aes256Key) -> Aes256CbcCodec {
-        let padding = Padding::Ansix923(Ansix923::new(0xff as u8));
-        let gkey = key.skey();
-        let giv = key.siv();
-        Aes256CbcCodec {
-            cipher: Aes256::new(&GenericArray::from(gkey)),
-            key: gkey,
-            iv: giv,
-            padding: padding,
-        }
+    pub fn new_with_key(key: &Aes256Key) -> Result<Aes256CbcCodec, Error> {
+        Ok(Aes256CbcCodec {
+            cipher: Aes256::new(GenericArray::from_slice(key.skey()?)),
+            iv: *key.siv()?,
+            padding: Padding::Ansix923(Ansix923::new(0xff)),
+        })
+    }
+    /// continues the cbc chain from `iv`, such as the last block of
+    /// the previous ciphertext
+    pub fn set_iv(&mut self, iv: &B128) {
+        self.iv.copy_from_slice(iv);
     }
     pub fn encrypt_first_block(&self, input_block: &[u8]) -> Vec<u8> {
         self.encrypt_block(input_block, &self.iv)
     }
     pub fn cipher(&self) -> Aes256 {
-        Aes256::new(&(self.key).into())
+        self.cipher.clone()
+    }
+    pub fn padding(&self) -> &Padding {
+        &self.padding
+    }
+}
+
+impl Drop for Aes256CbcCodec {
+    fn drop(&mut self) {
+        self.iv.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Aes256CbcCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Aes256CbcCodec")
+            .field("iv", &"<redacted>")
+            .field("padding", &self.padding)
+            .finish()
     }
 }
 
@@ -528,6 +835,9 @@ impl EncryptionEngine for Aes256CbcCodec {
         // split ciphertext into 16 blocks
         let chunks: Vec<Vec<u8>> = ciphertext.chunks(16).map(|c| c.to_vec()).collect();
         let count = chunks.len();
+        if count == 0 {
+            return Vec::new();
+        }
         let last_block_pos = count - 1;
 
         for (index, block) in chunks.iter().enumerate() {
@@ -557,21 +867,29 @@ impl EncryptionEngine for Aes256CbcCodec {
 
 #[cfg(test)]
 mod aes256cbc_tests {
-    use crate::aescbc::cdc::{xor_128, Aes256CbcCodec, Aes256Key, EncryptionEngine, B128, B256};
+    use crate::aescbc::cdc::{shuffle_with_seed, Aes256CbcCodec, LEGACY_IV, Aes256Key, EncryptionEngine, B128, B256};
+    use crate::aescbc::SecretBytes;
+    use crate::aescbc::xor::xor_128;
     use crate::aescbc::cdc::{AESMGPF, MK0, MK1};
     use crate::aescbc::kd::pbkdf2_sha384_128bits;
     use crate::aescbc::kd::pbkdf2_sha384_256bits;
     use crate::aescbc::kd::DerivationScheme;
     use crate::aescbc::kd::Pbkdf2HashingAlgo;
+    use crate::aescbc::config::{IvDerivation, Pbkdf2Config};
     use crate::hashis::CrcAlgo;
     use crate::ioutils::read_bytes;
     use crate::aescbc::VRSBUF;
+    use crate::aescbc::pv::{Provenance, SourceRecord};
+    use crate::sources::SecretSource;
+    use sha3::{Digest, Sha3_256};
 
     use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
     use aes::Aes256;
     use k9::assert_equal;
     use serde::{Deserialize, Serialize};
-    // use crate::errors::Error;
+    use crate::errors::Error;
+    use crate::emit::TempEmission;
+    use std::io::Write;
     use glob::glob;
 
     // use std::str::FromStr;
@@ -1071,12 +1389,8 @@ mod aes256cbc_tests {
         let salt = "soul society".to_string();
         let key = Aes256Key::derive(
             [password].to_vec(),
-            u64::MAX,
             [salt].to_vec(),
-            u64::MAX,
-            0x35,
-            DerivationScheme::Crc(CrcAlgo::GcRc256),
-            false,
+            Pbkdf2Config::new(DerivationScheme::Crc(CrcAlgo::GcRc256), 0x35, false, false),
             None
         )
         .expect("it appears that the key cannot be derived in this instant");
@@ -1086,7 +1400,7 @@ mod aes256cbc_tests {
         let plaintext_length = plaintext.len();
 
         // Given I initialize a Aes256CbcCodec with a Aes256Key
-        let cdc = Aes256CbcCodec::new_with_key(key);
+        let cdc = Aes256CbcCodec::new_with_key(&key).unwrap();
 
         // When I encrypt the combined plaintext
         let ciphertext = cdc.encrypt_blocks(&plaintext);
@@ -1107,12 +1421,8 @@ mod aes256cbc_tests {
         let salt = "capiti".to_string();
         let key = Aes256Key::derive(
             [password].to_vec(),
-            u64::MAX,
             [salt].to_vec(),
-            u64::MAX,
-            0x35,
-            DerivationScheme::Crc(CrcAlgo::GcRc256),
-            false,
+            Pbkdf2Config::new(DerivationScheme::Crc(CrcAlgo::GcRc256), 0x35, false, false),
             None
         )
             .unwrap();
@@ -1124,11 +1434,11 @@ mod aes256cbc_tests {
 
         let mut lhs = kdatum.len() - 16;
         let siv: Vec<u8> = kdatum.drain(lhs..).collect();
-        assert_equal!(siv.to_vec(), key.siv().to_vec());
+        assert_equal!(siv.to_vec(), key.siv().unwrap().to_vec());
 
         lhs -= 32;
         let skey: Vec<u8> = kdatum.drain(lhs..).collect();
-        assert_equal!(skey.to_vec(), key.skey().to_vec());
+        assert_equal!(skey.to_vec(), key.skey().unwrap().to_vec());
 
         lhs -= 8;
         let cycles =
@@ -1183,22 +1493,21 @@ mod aes256cbc_tests {
         let key = Aes256Key::derive_with_name(
             &filename,
             [password].to_vec(),
-            u64::MAX,
             [salt].to_vec(),
-            u64::MAX,
-            0x35,
-            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512),
-            false,
+            Pbkdf2Config::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512), 0x35, false, false),
             None
         )
         .unwrap();
         key.save_to_file(path.clone()).unwrap();
         let ck = Aes256Key::load_from_file(path, true, None, None, None, false).unwrap();
-        assert_equal!(ck.siv(), key.siv());
-        assert_equal!(ck.skey(), key.skey());
+        assert_equal!(ck.siv().unwrap(), key.siv().unwrap());
+        assert_equal!(ck.skey().unwrap(), key.skey().unwrap());
         assert_equal!(ck.sblob().len(), key.sblob().len());
         assert_equal!(ck.sblob(), key.sblob());
-        assert_equal!(key, ck);
+        // binary keys only keep the cycles of their derivation and the
+        // version of their layout
+        assert_equal!(ck.version, "obg-v3.0.1");
+        assert_equal!(Aes256Key { provenance: None, version: ck.version.clone(), ..key }, ck);
     }
     #[test]
     pub fn test_opaque_save_open_bloblenset() {
@@ -1212,22 +1521,21 @@ mod aes256cbc_tests {
         let key = Aes256Key::derive_with_name(
             &path,
             [password].to_vec(),
-            u64::MAX,
             [salt].to_vec(),
-            u64::MAX,
-            0x35,
-            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512),
-            false,
+            Pbkdf2Config::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512), 0x35, false, false),
             Some(325)
         )
         .unwrap();
         key.save_to_file(path.clone()).unwrap();
         let ck = Aes256Key::load_from_file(path, true, None, None, None, false).unwrap();
-        assert_equal!(ck.siv(), key.siv());
-        assert_equal!(ck.skey(), key.skey());
+        assert_equal!(ck.siv().unwrap(), key.siv().unwrap());
+        assert_equal!(ck.skey().unwrap(), key.skey().unwrap());
         assert_equal!(ck.sblob().len(), key.sblob().len());
         assert_equal!(ck.sblob(), key.sblob());
-        assert_equal!(key, ck);
+        // binary keys only keep the cycles of their derivation and the
+        // version of their layout
+        assert_equal!(ck.version, "obg-v3.0.1");
+        assert_equal!(Aes256Key { provenance: None, version: ck.version.clone(), ..key }, ck);
     }
     #[test]
     pub fn test_opaque_save_open_bloblenbehindmin() {
@@ -1241,12 +1549,8 @@ mod aes256cbc_tests {
         let result = Aes256Key::derive_with_name(
             &path,
             [password].to_vec(),
-            u64::MAX,
             [salt].to_vec(),
-            u64::MAX,
-            0x35,
-            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512),
-            false,
+            Pbkdf2Config::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512), 0x35, false, false),
             Some(33)
         );
 
@@ -1259,4 +1563,185 @@ mod aes256cbc_tests {
             }
         }, format!("InvalidVersion: blob length too small: 33 (min 237)"));
     }
+    #[test]
+    pub fn test_verify_derivation() -> Result<(), Error> {
+        let derive = |password: &str, salt: &str| {
+            Aes256Key::derive(
+                vec![password.to_string()],
+                vec![salt.to_string()],
+                Pbkdf2Config::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256), 0x35, true, false)
+                    .with_high_water_marks(0x400, 0x400),
+                None,
+            )
+        };
+        let key = derive("literal:arriviami", "file:tests/key.png")?;
+        let yaml = serde_yaml::to_string(&key)?;
+        assert!(!yaml.contains("arriviami"));
+        assert!(yaml.contains("kind: literal"));
+        let key: Aes256Key = serde_yaml::from_str(&yaml)?;
+
+        let verify = |key: &Aes256Key, password: &str, salt: &str| {
+            key.verify_derivation(
+                vec![SecretSource::parse(password)?],
+                0x400,
+                vec![SecretSource::parse(salt)?],
+                0x400,
+                DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_384),
+            )
+        };
+        verify(&key, "literal:arriviami", "file:tests/key.png")?;
+        assert!(verify(&key, "literal:capiti", "file:tests/key.png").is_err());
+        assert_equal!(
+            format!("{}", verify(&key, "literal:arriviami", "literal:capiti").unwrap_err()),
+            format!(
+                "KeyError: salt sources differ from those recorded: literal instead of file with sha3-256 {}",
+                hex::encode(Sha3_256::digest(&read_bytes("tests/key.png")?[..0x400]))
+            )
+        );
+
+        // password files are recorded without a digest
+        let key = derive("file:tests/key.png", "literal:capiti")?;
+        let passwords = match &key.provenance {
+            Some(Provenance { derivation: Some(derivation), .. }) => derivation.passwords.clone(),
+            _ => Vec::new(),
+        };
+        assert_equal!(passwords, vec![SourceRecord { kind: "file".to_string(), sha3_256: None }]);
+        assert!(!serde_yaml::to_string(&key)?.contains("sha3_256:"));
+        verify(&key, "file:tests/key.png", "literal:capiti")?;
+
+        // binary keys record their cycles but neither scheme nor sources
+        let binary = Aes256Key::from_bytes(derive("literal:arriviami", "literal:capiti")?.to_bytes("verify.kgz")?, true)?;
+        assert_equal!(binary.provenance, None);
+        assert!(verify(&binary, "literal:arriviami", "literal:capiti").is_err());
+        let verify_binary = |key: &Aes256Key| {
+            key.verify_derivation(
+                vec![SecretSource::parse("literal:arriviami")?],
+                0x400,
+                vec![SecretSource::parse("literal:capiti")?],
+                0x400,
+                DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256),
+            )
+        };
+        verify_binary(&binary)?;
+
+        // keys derived before the digest iv only record a legacy one
+        let legacy = Aes256Key::new(*binary.skey()?, LEGACY_IV, binary.sblob(), 0x35);
+        verify_binary(&legacy)?;
+        let mut provenance = derive("literal:arriviami", "literal:capiti")?.provenance.unwrap();
+        provenance.derivation.as_mut().unwrap().iv_derivation = IvDerivation::Legacy;
+        let yaml = serde_yaml::to_string(&Aes256Key { provenance: Some(provenance), ..legacy.clone() })?;
+        let recorded: Aes256Key = serde_yaml::from_str(&yaml.lines().filter(|line| !line.contains("iv_derivation")).collect::<Vec<_>>().join("\n"))?;
+        verify(&recorded, "literal:arriviami", "literal:capiti")?;
+        assert!(verify(&Aes256Key { provenance: derive("literal:arriviami", "literal:capiti")?.provenance, ..legacy }, "literal:arriviami", "literal:capiti").is_err());
+
+        assert!(verify(&Aes256Key::random(None)?, "literal:arriviami", "literal:capiti").is_err());
+        let derivation = Pbkdf2Config::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256), u32::MAX as u64 + 1, false, false);
+        assert!(Aes256Key::derive_from_bytes(b"arriviami", b"capiti", &derivation, None).is_err());
+        Ok(())
+    }
+    #[derive(Deserialize)]
+    struct DeterministicVector {
+        password: String,
+        salt: String,
+        scheme: DerivationScheme,
+        cycles: u64,
+        shuffle_iv: bool,
+        blob_length: Option<u64>,
+        key: String,
+        iv: String,
+        derived_blob_length: usize,
+        blob_sha3_256: String,
+        iv_derivation: IvDerivation,
+    }
+    #[test]
+    pub fn test_deterministic_vectors() -> Result<(), Error> {
+        let vectors: Vec<DeterministicVector> = serde_yaml::from_slice(&read_bytes("tests/deterministic-vectors.yml")?)?;
+        for vector in vectors {
+            let derivation = Pbkdf2Config::new(vector.scheme, vector.cycles, vector.shuffle_iv, true)
+                .with_iv_derivation(vector.iv_derivation);
+            let key = Aes256Key::derive_from_bytes(
+                vector.password.as_bytes(),
+                vector.salt.as_bytes(),
+                &derivation,
+                vector.blob_length,
+            )?;
+            assert_equal!(hex::encode(key.skey()?), vector.key);
+            assert_equal!(hex::encode(key.siv()?), vector.iv);
+            assert_equal!(key.sblob().len(), vector.derived_blob_length);
+            assert_equal!(hex::encode(Sha3_256::digest(key.sblob())), vector.blob_sha3_256);
+            if vector.shuffle_iv {
+                let unshuffled = Aes256Key::derive_from_bytes(
+                    vector.password.as_bytes(),
+                    vector.salt.as_bytes(),
+                    &Pbkdf2Config { shuffle_iv: false, ..derivation },
+                    vector.blob_length,
+                )?;
+                let (mut iv, mut unshuffled_iv) = (*key.siv()?, *unshuffled.siv()?);
+                assert!(iv != unshuffled_iv);
+                iv.sort();
+                unshuffled_iv.sort();
+                assert_equal!(iv, unshuffled_iv);
+            }
+        }
+        Ok(())
+    }
+    #[test]
+    pub fn test_shuffle_with_seed() {
+        let mut bytes: Vec<u8> = (0..16).collect();
+        shuffle_with_seed(&mut bytes, &[0x47; 15]);
+        assert_equal!(bytes, vec![0, 4, 2, 3, 12, 10, 9, 15, 8, 13, 5, 14, 6, 1, 11, 7]);
+    }
+    #[test]
+    pub fn test_load_reports_malformed_text_keys() -> Result<(), Error> {
+        let key = Aes256Key::new([0x47; 32], [0x54; 16], &[0x37; 237], 0);
+        let (mut file, path) = TempEmission::now().papobg_8473776564_file()?;
+        let yaml = serde_yaml::to_string(&key)?.replacen("key:", "kee:", 1);
+        file.write_all(yaml.as_bytes())?;
+        let error = Aes256Key::load(format!("{}", path.display())).unwrap_err();
+        assert!(matches!(error, Error::YamlFileError(_)));
+
+        let (_, path) = TempEmission::now().papobg_8473776564_file()?;
+        let path = format!("{}", path.display());
+        key.save_to_file(path.clone())?;
+        assert_equal!(Aes256Key::load(path)?.skey()?, key.skey()?);
+        Ok(())
+    }
+    #[test]
+    pub fn test_random_key() {
+        let key = Aes256Key::random(Some(271)).unwrap();
+        let other = Aes256Key::random(None).unwrap();
+        assert!(key.skey().unwrap() != other.skey().unwrap());
+        assert!(key.siv().unwrap() != other.siv().unwrap());
+        assert_equal!(key.sblob().len(), 271);
+        assert_equal!(key.cycles, None);
+        assert_equal!(key.provenance, Some(Provenance::random()));
+
+        let yaml = serde_yaml::to_string(&key).unwrap();
+        assert!(yaml.contains("origin: random"));
+        let parsed: Aes256Key = serde_yaml::from_str(&yaml).unwrap();
+        assert_equal!(parsed, key);
+        assert!(Aes256Key::random(Some(33)).is_err());
+    }
+    #[test]
+    pub fn test_debug_redacts_secrets() {
+        let key = Aes256Key::new([0x47; 32], [0x54; 16], &[0x37; 237], 0);
+        let debug = format!("{:?}", key);
+        assert!(!debug.contains("47, 47") && !debug.contains("4747"));
+        assert!(debug.contains("<32 bytes redacted>"));
+        assert!(!format!("{:?}", Aes256CbcCodec::new_with_key(&key).unwrap()).contains("84, 84"));
+    }
+    #[test]
+    pub fn test_invalid_key_hex_is_an_error() {
+        let mut key = Aes256Key::new([0x47; 32], [0x54; 16], &[], 0);
+        let yaml = serde_yaml::to_string(&key).unwrap().replace(&"47".repeat(32), "not hex");
+        assert!(serde_yaml::from_str::<Aes256Key>(&yaml).is_err());
+        key.key = SecretBytes::from(&[0x47; 31][..]);
+        key.iv = SecretBytes::from(&[0x54, 0x54][..]);
+        assert!(matches!(key.skey(), Err(Error::InvalidKeyHex(_))));
+        assert!(matches!(key.siv(), Err(Error::InvalidKeyHex(_))));
+        assert!(matches!(
+            Aes256Key::from_bytes(vec![0x35; 37], true),
+            Err(Error::NonValidKey(_))
+        ));
+    }
 }
@@ -1,6 +1,9 @@
+use crate::aescbc::kd::DerivationScheme;
+use crate::aescbc::pv::SourceRecord;
 use crate::aescbc::tp::{B128, B256};
+use crate::clap::DEFAULT_CYCLES;
 use crate::errors::Error;
-use crate::serial::YamlFile;
+use crate::serial::ConfigFile;
 // use crate::aescbc::tp::{b128_to_u64, b256_to_u128};
 
 use serde::{Deserialize, Serialize};
@@ -12,7 +15,7 @@ pub enum AesCbcPaddingMethod {
     Ansix923,
 }
 
-impl YamlFile for AesCbcPaddingMethod {
+impl ConfigFile for AesCbcPaddingMethod {
     fn default() -> Result<AesCbcPaddingMethod, Error> {
         Ok(AesCbcPaddingMethod::Ansix923)
     }
@@ -41,7 +44,7 @@ impl AesCbcPaddingConfig {
     }
 }
 
-impl YamlFile for AesCbcPaddingConfig {
+impl ConfigFile for AesCbcPaddingConfig {
     fn default() -> Result<AesCbcPaddingConfig, Error> {
         Ok(AesCbcPaddingConfig::new(
             0xff,
@@ -94,7 +97,7 @@ impl fmt::Display for Pbkdf2HashingAlgo {
     }
 }
 
-impl YamlFile for Pbkdf2HashingAlgo {
+impl ConfigFile for Pbkdf2HashingAlgo {
     fn default() -> Result<Pbkdf2HashingAlgo, Error> {
         Ok(Pbkdf2HashingAlgo::Sha3_384)
     }
@@ -113,34 +116,89 @@ pub trait Pbkdf2Hasher<Block> {
     fn pbkdf2_hash(pw: &[u8], st: &[u8], it: u32) -> Block;
 }
 
+/// how the iv of a derived key is obtained, records which lack it
+/// predate obg 4.2 and are [`IvDerivation::Legacy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IvDerivation {
+    /// the leading filler of the blob, the same 16 bytes for every key
+    #[default]
+    Legacy,
+    /// SHA3-256 of the key and of the checksums the blob is made of
+    Digest,
+}
+
+impl fmt::Display for IvDerivation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                IvDerivation::Legacy => "legacy",
+                IvDerivation::Digest => "digest",
+            }
+        )
+    }
+}
+
+/// how a key was derived, never including the password or the salt
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Pbkdf2Config {
-    pub password: String,
-    pub password_hashing: Pbkdf2HashingAlgo,
-    pub salt: String,
-    pub salt_hashing: Pbkdf2HashingAlgo,
-    pub iterations: u32,
-    pub length: Pbkdf2BlockLength,
-}
-impl YamlFile for Pbkdf2Config {
+    pub scheme: DerivationScheme,
+    pub cycles: u64,
+    #[serde(default)]
+    pub iv_derivation: IvDerivation,
+    #[serde(default)]
+    pub shuffle_iv: bool,
+    #[serde(default)]
+    pub deterministic: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hwm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_hwm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passwords: Vec<SourceRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub salts: Vec<SourceRecord>,
+}
+impl Pbkdf2Config {
+    /// a derivation out of bytes whose sources are unknown, with the
+    /// iv derivation of current versions
+    pub fn new(scheme: DerivationScheme, cycles: u64, shuffle_iv: bool, deterministic: bool) -> Pbkdf2Config {
+        Pbkdf2Config {
+            scheme,
+            cycles,
+            iv_derivation: IvDerivation::Digest,
+            shuffle_iv,
+            deterministic,
+            password_hwm: None,
+            salt_hwm: None,
+            passwords: Vec::new(),
+            salts: Vec::new(),
+        }
+    }
+    pub fn with_iv_derivation(self, iv_derivation: IvDerivation) -> Pbkdf2Config {
+        Pbkdf2Config { iv_derivation, ..self }
+    }
+    /// the high-water marks the password and salt sources are read up to
+    pub fn with_high_water_marks(self, password_hwm: u64, salt_hwm: u64) -> Pbkdf2Config {
+        Pbkdf2Config {
+            password_hwm: Some(password_hwm),
+            salt_hwm: Some(salt_hwm),
+            ..self
+        }
+    }
+}
+impl ConfigFile for Pbkdf2Config {
     fn default() -> Result<Pbkdf2Config, Error> {
-        Ok(Pbkdf2Config {
-            password: String::new(),
-            password_hashing: Pbkdf2HashingAlgo::default()?,
-            salt: String::new(),
-            salt_hashing: Pbkdf2HashingAlgo::default()?,
-            iterations: 1337,
-            length: Pbkdf2BlockLength::L128,
-        })
-    }
-}
-
-// impl Pbkdf2Config {
-//     pub fn for_key_from_file(password_file: &Path, salt_file: Path) -> Pbkdf2Config {
-//         Pbkdf2Config {
-//         }
-//     }
-// }
+        Ok(Pbkdf2Config::new(
+            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::default()?),
+            DEFAULT_CYCLES,
+            false,
+            false,
+        ))
+    }
+}
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Aes256CbcConfig {
@@ -0,0 +1,131 @@
+/*!
+Opaque Key Descriptors
+
+Records which file an opaque key is carved from and at which offsets,
+along with a SHA3-256 digest of that carrier file, such that
+`-k descriptor.yml` reproduces the key without remembering the offsets
+and refuses a carrier which changed since.
+*/
+use crate::aescbc::cdc::Aes256Key;
+use crate::errors::Error;
+use crate::ioutils::{absolute_path, open_write, read_bytes};
+use crate::serial::{self, FileFormat};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::io::Write;
+use zeroize::Zeroizing;
+
+fn getcurrentversion() -> String {
+    format!("obg-v{}", env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpaqueDescriptor {
+    pub version: String,
+    /// absolute path of the file the key is carved from
+    pub carrier: String,
+    /// hex-encoded SHA3-256 digest of the carrier
+    pub sha3_256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_offset: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_offset: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_offset: Option<usize>,
+    #[serde(default)]
+    pub middle_out: bool,
+}
+
+impl OpaqueDescriptor {
+    /// describes the key carved out of `carrier` as it currently is
+    pub fn describe(
+        carrier: &str,
+        key_offset: Option<usize>,
+        salt_offset: Option<usize>,
+        blob_offset: Option<usize>,
+        middle_out: bool,
+    ) -> Result<OpaqueDescriptor, Error> {
+        let carrier = std::path::absolute(absolute_path(carrier))?.to_string_lossy().to_string();
+        let bytes = Zeroizing::new(read_bytes(&carrier)?);
+        Ok(OpaqueDescriptor {
+            version: getcurrentversion(),
+            sha3_256: hex::encode(Sha3_256::digest(&*bytes)),
+            carrier,
+            key_offset,
+            salt_offset,
+            blob_offset,
+            middle_out,
+        })
+    }
+    /// carves the key out of the carrier after checking its digest
+    pub fn load_key(&self) -> Result<Aes256Key, Error> {
+        let mut bytes = Zeroizing::new(read_bytes(&self.carrier)?);
+        let digest = hex::encode(Sha3_256::digest(&*bytes));
+        if digest != self.sha3_256.to_lowercase() {
+            return Err(Error::KeyError(format!(
+                "carrier {} changed since it was described: sha3-256 is {} instead of {}",
+                self.carrier, digest, self.sha3_256
+            )));
+        }
+        Aes256Key::from_carrier_bytes(
+            std::mem::take(&mut *bytes),
+            false,
+            self.key_offset,
+            self.salt_offset,
+            self.blob_offset,
+            self.middle_out,
+        )
+    }
+    pub fn save_to_yaml_file(&self, filename: String) -> Result<(), Error> {
+        let mut file = open_write(&filename)?;
+        let yaml = serde_yaml::to_string(self)?;
+        Ok(file.write_all(yaml.as_bytes())?)
+    }
+    pub fn load_from_yaml_file(filename: String) -> Result<OpaqueDescriptor, Error> {
+        let bytes = read_bytes(&filename)?;
+        let descriptor: OpaqueDescriptor = serde_yaml::from_slice(&bytes)?;
+        Ok(descriptor)
+    }
+    /// writes the descriptor as yaml, json or toml
+    pub fn save_to_config_file(&self, filename: String, format: FileFormat) -> Result<(), Error> {
+        serial::save(self, &filename, format)?;
+        Ok(())
+    }
+    /// reads a yaml, json or toml descriptor, see [`serial::load`]
+    pub fn load_from_config_file(filename: String) -> Result<OpaqueDescriptor, Error> {
+        serial::load(&filename)
+    }
+}
+
+#[cfg(test)]
+mod descriptor_tests {
+    use crate::aescbc::cdc::Aes256Key;
+    use crate::aescbc::descriptor::OpaqueDescriptor;
+    use crate::emit::TempEmission;
+    use crate::errors::Error;
+    use crate::serial::FileFormat;
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_descriptor_reproduces_opaque_key() -> Result<(), Error> {
+        let (_, path) = TempEmission::now().papobg_8473776564_file()?;
+        let carrier = &format!("{}", path.display());
+        std::fs::copy("tests/nothing.png", carrier)?;
+        let descriptor = OpaqueDescriptor::describe(carrier, Some(83), Some(61), Some(78), false)?;
+        let descriptor_file = format!("{}.json", carrier);
+        descriptor.save_to_config_file(descriptor_file.clone(), FileFormat::Json)?;
+        assert_equal!(OpaqueDescriptor::load_from_config_file(descriptor_file.clone())?, descriptor);
+
+        let expected = Aes256Key::load_from_file(carrier.to_string(), false, Some(83), Some(61), Some(78), false)?;
+        let key = Aes256Key::load(descriptor_file)?;
+        assert_equal!(key.skey()?, expected.skey()?);
+        assert_equal!(key.siv()?, expected.siv()?);
+
+        let mut bytes = std::fs::read(carrier)?;
+        bytes[0] ^= 0x01;
+        std::fs::write(carrier, bytes)?;
+        assert!(matches!(descriptor.load_key(), Err(Error::KeyError(_))));
+        Ok(())
+    }
+}
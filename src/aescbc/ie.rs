@@ -0,0 +1,242 @@
+/*!
+Import/Export of keys in interoperable encodings
+
+Only the binary, yaml, json and toml formats carry the blob, cycles
+and version of an [`Aes256Key`], the remaining formats carry the raw
+key and iv.
+*/
+use crate::aescbc::cdc::Aes256Key;
+use crate::aescbc::tp::{B128, B256};
+use crate::errors::Error;
+use crate::serial::{self, FileFormat};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum KeyFormat {
+    /// obg's binary layout
+    Binary,
+    /// obg's yaml layout
+    Yaml,
+    /// obg's yaml layout as json
+    Json,
+    /// obg's yaml layout as toml
+    Toml,
+    /// 32 bytes of key followed by 16 bytes of iv
+    Raw,
+    /// hex-encoded key followed by iv
+    Hex,
+    /// base64-encoded key followed by iv
+    Base64,
+    /// JSON Web Key of type "oct" with the iv as extra member
+    Jwk,
+    /// arguments to `openssl enc -aes-256-cbc`
+    Openssl,
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                KeyFormat::Binary => "binary",
+                KeyFormat::Yaml => "yaml",
+                KeyFormat::Json => "json",
+                KeyFormat::Toml => "toml",
+                KeyFormat::Raw => "raw",
+                KeyFormat::Hex => "hex",
+                KeyFormat::Base64 => "base64",
+                KeyFormat::Jwk => "jwk",
+                KeyFormat::Openssl => "openssl",
+            }
+        )
+    }
+}
+
+impl KeyFormat {
+    /// whether keys in this format keep their blob, cycles and version
+    pub fn carries_blob(&self) -> bool {
+        matches!(
+            self,
+            KeyFormat::Binary | KeyFormat::Yaml | KeyFormat::Json | KeyFormat::Toml
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub alg: String,
+    pub k: String,
+    pub iv: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+}
+
+fn key_material(key: &Aes256Key) -> Result<Vec<u8>, Error> {
+    let mut material = Vec::with_capacity(48);
+    material.extend(key.skey()?);
+    material.extend(key.siv()?);
+    Ok(material)
+}
+
+fn from_material(format: &KeyFormat, material: &[u8]) -> Result<Aes256Key, Error> {
+    if material.len() != 48 {
+        return Err(Error::KeyError(format!(
+            "{} key material is {} bytes long instead of 48",
+            format,
+            material.len()
+        )));
+    }
+    from_parts(&material[..32], &material[32..])
+}
+
+fn from_parts(key: &[u8], iv: &[u8]) -> Result<Aes256Key, Error> {
+    if key.len() != 32 {
+        return Err(Error::InvalidAes256KeySize(format!(
+            "key length is {} instead of 32",
+            key.len()
+        )));
+    }
+    if iv.len() != 16 {
+        return Err(Error::InvalidAesIvSize(format!(
+            "iv length is {} instead of 16",
+            iv.len()
+        )));
+    }
+    let mut skey: B256 = [0; 32];
+    skey.copy_from_slice(key);
+    let mut siv: B128 = [0; 16];
+    siv.copy_from_slice(iv);
+    Ok(Aes256Key::new(skey, siv, &[], 0))
+}
+
+/// encodes `key` in `format`, `filename` is recorded by the binary layout
+pub fn export_key(key: &Aes256Key, format: &KeyFormat, filename: &str) -> Result<Vec<u8>, Error> {
+    Ok(match format {
+        KeyFormat::Binary => key.to_bytes(filename)?,
+        KeyFormat::Yaml => serde_yaml::to_string(key)?.into_bytes(),
+        KeyFormat::Json => serial::to_string(key, FileFormat::Json)?.into_bytes(),
+        KeyFormat::Toml => serial::to_string(key, FileFormat::Toml)?.into_bytes(),
+        KeyFormat::Raw => key_material(key)?,
+        KeyFormat::Hex => format!("{}\n", hex::encode(key_material(key)?)).into_bytes(),
+        KeyFormat::Base64 => format!("{}\n", STANDARD.encode(key_material(key)?)).into_bytes(),
+        KeyFormat::Jwk => {
+            let jwk = Jwk {
+                kty: "oct".to_string(),
+                alg: "A256CBC".to_string(),
+                k: URL_SAFE_NO_PAD.encode(key.skey()?),
+                iv: URL_SAFE_NO_PAD.encode(key.siv()?),
+                kid: key.name(),
+            };
+            match serde_json::to_string_pretty(&jwk) {
+                Ok(json) => format!("{}\n", json).into_bytes(),
+                Err(e) => return Err(Error::SerializationError(e)),
+            }
+        }
+        KeyFormat::Openssl => format!(
+            "-K {} -iv {}\n",
+            hex::encode(key.skey()?),
+            hex::encode(key.siv()?)
+        )
+        .into_bytes(),
+    })
+}
+
+/// decodes a key previously encoded in `format`
+pub fn import_key(data: &[u8], format: &KeyFormat) -> Result<Aes256Key, Error> {
+    let text = || -> Result<String, Error> { Ok(String::from_utf8(data.to_vec())?.trim().to_string()) };
+    match format {
+        KeyFormat::Binary => Aes256Key::from_bytes(data.to_vec(), false),
+        KeyFormat::Yaml => Ok(serde_yaml::from_slice(data)?),
+        KeyFormat::Json => serial::from_str(&String::from_utf8(data.to_vec())?, FileFormat::Json),
+        KeyFormat::Toml => serial::from_str(&String::from_utf8(data.to_vec())?, FileFormat::Toml),
+        KeyFormat::Raw => from_material(format, data),
+        KeyFormat::Hex => from_material(format, &hex::decode(text()?)?),
+        KeyFormat::Base64 => match STANDARD.decode(text()?) {
+            Ok(material) => from_material(format, &material),
+            Err(e) => Err(Error::DeserializationError(format!("invalid base64: {}", e))),
+        },
+        KeyFormat::Jwk => {
+            let jwk: Jwk = match serde_json::from_slice(data) {
+                Ok(jwk) => jwk,
+                Err(e) => return Err(Error::DeserializationError(e.to_string())),
+            };
+            if jwk.kty != "oct" {
+                return Err(Error::KeyError(format!(
+                    "unsupported JWK key type {:?}",
+                    jwk.kty
+                )));
+            }
+            let decode = |value: &str| match URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')) {
+                Ok(bytes) => Ok(bytes),
+                Err(e) => Err(Error::DeserializationError(format!("invalid base64url: {}", e))),
+            };
+            from_parts(&decode(&jwk.k)?, &decode(&jwk.iv)?)
+        }
+        KeyFormat::Openssl => {
+            let text = text()?;
+            let mut key = None;
+            let mut iv = None;
+            let mut words = text.split_whitespace();
+            while let Some(word) = words.next() {
+                match word {
+                    "-K" => key = words.next(),
+                    "-iv" => iv = words.next(),
+                    _ => {}
+                }
+            }
+            match (key, iv) {
+                (Some(key), Some(iv)) => from_parts(&hex::decode(key)?, &hex::decode(iv)?),
+                _ => Err(Error::KeyError(
+                    "openssl key requires both -K and -iv".to_string(),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod key_import_export_tests {
+    use crate::aescbc::cdc::Aes256Key;
+    use crate::aescbc::ie::{export_key, import_key, KeyFormat};
+    use crate::errors::Error;
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_roundtrip_all_formats() -> Result<(), Error> {
+        let key = Aes256Key::new([0x47; 32], [0x54; 16], &[0x37; 237], 0x35);
+        for format in [
+            KeyFormat::Raw,
+            KeyFormat::Hex,
+            KeyFormat::Base64,
+            KeyFormat::Jwk,
+            KeyFormat::Openssl,
+        ] {
+            let imported = import_key(&export_key(&key, &format, "key.kgz")?, &format)?;
+            assert!(!format.carries_blob());
+            assert_equal!(imported.skey()?, key.skey()?);
+            assert_equal!(imported.siv()?, key.siv()?);
+        }
+        for format in [KeyFormat::Binary, KeyFormat::Yaml, KeyFormat::Json, KeyFormat::Toml] {
+            let imported = import_key(&export_key(&key, &format, "key.kgz")?, &format)?;
+            assert!(format.carries_blob());
+            assert_equal!(imported.fingerprint()?, key.fingerprint()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_export_openssl() -> Result<(), Error> {
+        let key = Aes256Key::new([0x47; 32], [0x54; 16], &[], 0);
+        assert_equal!(
+            String::from_utf8(export_key(&key, &KeyFormat::Openssl, "key.kgz")?)?,
+            "-K 4747474747474747474747474747474747474747474747474747474747474747 -iv 54545454545454545454545454545454\n"
+        );
+        Ok(())
+    }
+}
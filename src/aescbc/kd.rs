@@ -3,7 +3,7 @@ use crate::aescbc::tp::B128;
 use crate::aescbc::tp::B256;
 use crate::errors::Error;
 use crate::hashis::{gcrc128, gcrc256, CrcAlgo};
-use crate::serial::YamlFile;
+use crate::serial::ConfigFile;
 use clap::builder::PossibleValue;
 use clap::ValueEnum;
 use pbkdf2::pbkdf2_hmac;
@@ -12,6 +12,7 @@ use sha3::Sha3_256 as Sha256;
 use sha3::Sha3_384 as Sha384;
 use sha3::Sha3_512 as Sha512;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 pub fn pbkdf2_sha256(data: &[u8], st: &[u8], it: u32, length: usize) -> Vec<u8> {
     let mut key: Vec<u8> = Vec::with_capacity(length);
@@ -103,7 +104,7 @@ pub fn pbkdf2_sha512_256bits(data: &[u8], st: &[u8], it: u32) -> B256 {
     result
 }
 
-#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum DerivationScheme {
     #[serde(rename = "pbkdf2")]
@@ -127,6 +128,51 @@ impl DerivationScheme {
             DerivationScheme::Crc(CrcAlgo::GcRc256) => gcrc256(data).to_vec(),
         }
     }
+    /// stable byte identifying the scheme within file headers
+    pub fn id(&self) -> u8 {
+        match self {
+            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256) => 0x01,
+            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_384) => 0x02,
+            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512) => 0x03,
+            DerivationScheme::Crc(CrcAlgo::GcRc128) => 0x11,
+            DerivationScheme::Crc(CrcAlgo::GcRc256) => 0x12,
+        }
+    }
+    pub fn from_id(id: u8) -> Option<DerivationScheme> {
+        Self::value_variants().iter().find(|ds| ds.id() == id).cloned()
+    }
+    /// whether the scheme stretches the password with a salt and cycles
+    pub fn is_stretching(&self) -> bool {
+        matches!(self, DerivationScheme::Pbkdf2(_))
+    }
+    /// times the derivation on this machine and returns the amount
+    /// of cycles which takes about `target`
+    pub fn calibrate(&self, target: Duration) -> Result<u64, Error> {
+        if !self.is_stretching() {
+            return Err(Error::InvalidCliArg(format!(
+                "{} takes no cycles and cannot be calibrated",
+                self
+            )));
+        }
+        let probe = [0x35u8; 32];
+        let mut cycles: u32 = 1024;
+        let floor = std::cmp::max(target / 16, Duration::from_millis(25));
+        loop {
+            let start = Instant::now();
+            self.derive(&probe, &probe, cycles);
+            let elapsed = start.elapsed();
+            if elapsed >= floor || cycles >= u32::MAX / 2 {
+                return Ok(scale_cycles(cycles, elapsed, target));
+            }
+            cycles *= 2;
+        }
+    }
+}
+
+/// the cycles lasting `target` given that `cycles` lasted `elapsed`
+fn scale_cycles(cycles: u32, elapsed: Duration, target: Duration) -> u64 {
+    let estimate = cycles as f64 * target.as_secs_f64() / elapsed.as_secs_f64().max(1e-9);
+    estimate.clamp(1.0, u32::MAX as f64) as u64
 }
 impl ValueEnum for DerivationScheme {
     fn value_variants<'a>() -> &'a [Self] {
@@ -172,6 +218,35 @@ impl ValueEnum for DerivationScheme {
     }
 }
 #[cfg(test)]
+mod derivation_scheme_calibration_tests {
+    use crate::aescbc::kd::{scale_cycles, DerivationScheme};
+    use crate::aescbc::kd::Pbkdf2HashingAlgo;
+    use crate::errors::Error;
+    use crate::hashis::CrcAlgo;
+    use k9::assert_equal;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_calibrate() -> Result<(), Error> {
+        let ds = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256);
+        let cycles = ds.calibrate(Duration::from_millis(20))?;
+        assert!(cycles > 0 && cycles <= u32::MAX as u64);
+        assert!(DerivationScheme::Crc(CrcAlgo::GcRc128).calibrate(Duration::from_secs(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_scale_cycles() {
+        let elapsed = Duration::from_millis(25);
+        assert_equal!(scale_cycles(1024, elapsed, Duration::from_millis(50)), 2048);
+        assert!(
+            scale_cycles(1024, elapsed, Duration::from_millis(200)) > scale_cycles(1024, elapsed, Duration::from_millis(20))
+        );
+        assert_equal!(scale_cycles(1024, elapsed, Duration::from_secs(1 << 40)), u32::MAX as u64);
+        assert_equal!(scale_cycles(1024, elapsed, Duration::ZERO), 1);
+    }
+}
+#[cfg(test)]
 mod derivation_scheme_serialization_tests {
     use crate::aescbc::config::Pbkdf2HashingAlgo;
     use crate::aescbc::kd::DerivationScheme;
@@ -216,7 +291,7 @@ mod derivation_scheme_serialization_tests {
 
 //     }
 // }
-impl YamlFile for DerivationScheme {
+impl ConfigFile for DerivationScheme {
     fn default() -> Result<DerivationScheme, Error> {
         Ok(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_512))
     }
@@ -13,18 +13,26 @@
 !*/
 pub mod cdc;
 pub mod config;
+pub mod descriptor;
 pub mod gb;
+pub mod ie;
 pub mod kd;
 pub mod pad;
+pub mod pv;
 pub mod rng;
+pub mod secret;
 pub mod tp;
-pub mod xor;
+mod xor;
 
 pub use cdc::*;
 pub use kd::*;
 pub use config::*;
+pub use descriptor::OpaqueDescriptor;
 pub use gb::*;
+pub use ie::*;
 pub use kd::*;
 pub use pad::*;
+pub use pv::*;
 pub use rng::*;
+pub use secret::{SecretArray, SecretBytes};
 pub use tp::*;
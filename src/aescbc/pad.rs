@@ -1,9 +1,15 @@
-// use crate::errors::Error;
 use crate::aescbc::tp::B128;
+use crate::errors::{DecryptionError, Error};
 pub trait Padder128 {
     fn pad(&self, msg: &[u8]) -> B128;
     fn unpad(&self, msg: &[u8]) -> Vec<u8>;
     fn padbyte(&self) -> u8;
+    /// bytes which complete `length` to whole blocks, a whole block
+    /// when it already is such that the padding is always present
+    fn padding(&self, length: usize) -> Vec<u8>;
+    /// removes the [`Padder128::padding`] which ends `msg`, failing
+    /// rather than leaving `msg` untouched when it is malformed
+    fn strip(&self, msg: &[u8]) -> Result<Vec<u8>, Error>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -27,6 +33,16 @@ impl Padder128 for Padding {
             Padding::Ansix923(engine) => engine.padbyte(),
         }
     }
+    fn padding(&self, length: usize) -> Vec<u8> {
+        match self {
+            Padding::Ansix923(engine) => engine.padding(length),
+        }
+    }
+    fn strip(&self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Padding::Ansix923(engine) => engine.strip(msg),
+        }
+    }
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Ansix923 {
@@ -67,7 +83,7 @@ impl Padder128 for Ansix923 {
         for pad_pos in (abs_len - pad_len)..(abs_len - 1) {
             let padchar = msg[pad_pos];
             if padchar != padbyte {
-                // XXX: if feature[validation] return Error::UnpadError
+                // blocks which were not padded may look like they are, see [`Padder128::strip`]
                 return msg.to_vec();
             }
         }
@@ -81,6 +97,31 @@ impl Padder128 for Ansix923 {
     fn padbyte(&self) -> u8 {
         self.rc
     }
+    fn padding(&self, length: usize) -> Vec<u8> {
+        let remaining = 16 - length % 16;
+        let mut padding = vec![self.padbyte(); remaining];
+        padding[remaining - 1] = remaining as u8;
+        padding
+    }
+    fn strip(&self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let pad_len = msg.last().map(|last| *last as usize).unwrap_or(0);
+        if pad_len == 0 || pad_len > 16 || pad_len > msg.len() {
+            return Err(DecryptionError::new(format!(
+                "invalid ANSI X9.23 padding length {}",
+                pad_len
+            ))
+            .into());
+        }
+        let cut_pos = msg.len() - pad_len;
+        if msg[cut_pos..msg.len() - 1].iter().any(|padchar| *padchar != self.padbyte()) {
+            return Err(DecryptionError::new(format!(
+                "invalid ANSI X9.23 padding of {} bytes",
+                pad_len
+            ))
+            .into());
+        }
+        Ok(msg[..cut_pos].to_vec())
+    }
 }
 
 #[cfg(test)]
@@ -242,4 +283,56 @@ mod padder128_tests {
         ];
         assert_equal!(result, expected_block);
     }
+
+    #[test]
+    pub fn test_padding_always_present() {
+        // Given a padder with 0xff as padbyte
+        let padder = Ansix923::new(0xff);
+
+        // Then a remainder of 12 bytes is completed by 4 bytes
+        assert_equal!(padder.padding(12), vec![0xff, 0xff, 0xff, 0x04]);
+        // And whole blocks get a whole block of padding
+        let mut whole = vec![0xff; 15];
+        whole.push(0x10);
+        assert_equal!(padder.padding(32), whole);
+        assert_equal!(padder.padding(0), whole);
+    }
+
+    #[test]
+    pub fn test_strip_padding() {
+        // Given a padder with 0xff as padbyte
+        let padder = Ansix923::new(0xff);
+        let block12 = [
+            0x1c, 0xeb, 0x00, 0xda, 0xde, 0xad, 0xbe, 0xef, 0xca, 0xfe, 0xfa, 0xde,
+        ];
+
+        // When I strip the padding of padded blocks
+        for msg in [&block12[..], &[], &[0x42; 16]] {
+            let mut padded = msg.to_vec();
+            padded.extend(padder.padding(msg.len()));
+
+            // Then the original message is returned
+            assert_equal!(padder.strip(&padded).unwrap(), msg.to_vec());
+        }
+    }
+
+    #[test]
+    pub fn test_strip_invalid_padding() {
+        // Given a padder with 0xff as padbyte
+        let padder = Ansix923::new(0xff);
+
+        // Then a padbyte mismatch, a tail larger than a block and a null tail are refused
+        let mismatch: B128 = [
+            0x1c, 0xeb, 0x00, 0xda, 0xde, 0xad, 0xbe, 0xef, 0xca, 0xfe, 0xfa, 0xde, 0xff, 0xf5,
+            0xff, 0x04,
+        ];
+        assert!(padder.strip(&mismatch).is_err());
+        let mut too_long = mismatch;
+        too_long[15] = 0x11;
+        assert!(padder.strip(&too_long).is_err());
+        let mut null = mismatch;
+        null[15] = 0x00;
+        assert!(padder.strip(&null).is_err());
+        assert!(padder.strip(&[]).is_err());
+    }
 }
@@ -0,0 +1,104 @@
+/*!
+Key Provenance
+
+Records how a key came to be so tooling can tell keys derived from
+passwords apart from keys drawn out of the OS random number generator.
+Derived keys also record the scheme, cycles, high-water marks and
+the kinds of their password and salt sources such that `keygen
+--verify` can later prove how they were produced. Secrets are never
+recorded: only salt files are identified by a digest. Password files
+are not, an unsalted digest of one would let anyone holding the key
+test candidate files or guess short ones far faster than through the
+derivation.
+
+Provenance is carried by yaml, json and toml keys. The binary layout
+has no room for it: `keygen --random` therefore never writes binary
+keys and derived binary keys only keep their cycles.
+*/
+use crate::aescbc::config::Pbkdf2Config;
+use crate::sources::SecretSource;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyOrigin {
+    #[serde(rename = "random")]
+    Random,
+    #[serde(rename = "derived")]
+    Derived,
+}
+
+impl fmt::Display for KeyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                KeyOrigin::Random => "random",
+                KeyOrigin::Derived => "derived",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub origin: KeyOrigin,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation: Option<Pbkdf2Config>,
+}
+
+impl Provenance {
+    pub fn random() -> Provenance {
+        Provenance {
+            origin: KeyOrigin::Random,
+            derivation: None,
+        }
+    }
+    pub fn derived(derivation: Pbkdf2Config) -> Provenance {
+        Provenance {
+            origin: KeyOrigin::Derived,
+            derivation: Some(derivation),
+        }
+    }
+}
+
+/// a password or salt source as recorded in the provenance of a key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceRecord {
+    pub kind: String,
+    /// hex-encoded SHA3-256 digest of the bytes read out of salt files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha3_256: Option<String>,
+}
+
+impl SourceRecord {
+    /// records only the kind of a password source
+    pub fn password(source: &SecretSource) -> SourceRecord {
+        SourceRecord {
+            kind: source.kind().to_string(),
+            sha3_256: None,
+        }
+    }
+    /// records the kind of a salt source and the digest of salt files
+    pub fn salt(source: &SecretSource, bytes: &[u8]) -> SourceRecord {
+        let kind = source.kind();
+        SourceRecord {
+            kind: kind.to_string(),
+            sha3_256: match kind {
+                "file" => Some(hex::encode(Sha3_256::digest(bytes))),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for SourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.sha3_256 {
+            Some(digest) => write!(f, "{} with sha3-256 {}", self.kind, digest),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
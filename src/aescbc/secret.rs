@@ -0,0 +1,100 @@
+/*!
+Secret Bytes
+
+Key material which is wiped when dropped and redacted from `Debug`.
+Serde goes through [`hex`] whose intermediate strings are wiped as well.
+*/
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+#[derive(Default, PartialEq, Eq)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    /// see [`crate::harden::lock`]
+    pub fn new(bytes: Vec<u8>) -> SecretBytes {
+        crate::harden::lock(&bytes);
+        SecretBytes(bytes)
+    }
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+    /// the first `N` bytes, `None` if there are fewer
+    pub fn leading<const N: usize>(&self) -> Option<&[u8; N]> {
+        self.0.first_chunk::<N>()
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> SecretBytes {
+        SecretBytes::new(self.0.clone())
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> SecretBytes {
+        SecretBytes::new(bytes.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> SecretBytes {
+        SecretBytes::new(bytes)
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretBytes(<{} bytes redacted>)", self.0.len())
+    }
+}
+
+/// `#[serde(with = "crate::aescbc::secret::hex")]` for [`SecretBytes`]
+pub mod hex {
+    use super::SecretBytes;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use zeroize::Zeroizing;
+
+    pub fn serialize<S: Serializer>(secret: &SecretBytes, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = Zeroizing::new(::hex::encode(secret.expose()));
+        serializer.serialize_str(&encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SecretBytes, D::Error> {
+        let encoded = Zeroizing::new(String::deserialize(deserializer)?);
+        match ::hex::decode(encoded.as_bytes()) {
+            Ok(bytes) => Ok(SecretBytes::new(bytes)),
+            Err(e) => Err(D::Error::custom(format!("invalid hex: {}", e))),
+        }
+    }
+}
+
+/// an array which is wiped when dropped, for copies of key material
+pub type SecretArray<const N: usize> = Zeroizing<[u8; N]>;
+
+#[cfg(test)]
+mod secret_tests {
+    use crate::aescbc::secret::SecretBytes;
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_secret_bytes() {
+        let secret = SecretBytes::from(&[0x47u8; 20][..]);
+        assert_equal!(format!("{:?}", secret), "SecretBytes(<20 bytes redacted>)");
+        assert_equal!(secret.leading::<16>(), Some(&[0x47u8; 16]));
+        assert_equal!(secret.leading::<32>(), None);
+    }
+}
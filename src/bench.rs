@@ -0,0 +1,184 @@
+/*!
+Throughput Benchmarks
+
+Measures how fast the codecs, key derivations and checksums run on the
+current machine so batch jobs can be sized and regressions spotted
+between releases.
+*/
+use crate::aescbc::kd::{DerivationScheme, Pbkdf2HashingAlgo};
+use crate::aescbc::{Aes256CbcCodec, Aes256Key, EncryptionEngine};
+use crate::ccs::ChaCha20Key;
+use crate::errors::Error;
+use crate::hashis::{gcrc128, gcrc256};
+use crate::pap::{seal, unseal};
+use chacha20::cipher::StreamCipher;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256, Sha3_512};
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SIZES: [usize; 3] = [0x400, 0x10000, 0x100000];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub group: String,
+    pub name: String,
+    /// buffer size in bytes, absent for key derivations
+    pub size: Option<usize>,
+    pub iterations: u64,
+    pub elapsed_secs: f64,
+    /// MB/s for buffers, cycles per second for key derivations
+    pub rate: f64,
+    pub unit: String,
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<6} {:<22} {:>9} {:>14.2} {}",
+            self.group,
+            self.name,
+            match self.size {
+                Some(size) => format!("{}", size),
+                None => "-".to_string(),
+            },
+            self.rate,
+            self.unit
+        )
+    }
+}
+
+/// calls `run` until `duration` elapses and returns how many times it ran
+fn repeat<F: FnMut()>(duration: Duration, mut run: F) -> (u64, f64) {
+    let start = Instant::now();
+    let mut iterations = 0u64;
+    while iterations == 0 || start.elapsed() < duration {
+        run();
+        iterations += 1;
+    }
+    (iterations, start.elapsed().as_secs_f64())
+}
+
+fn throughput<F: FnMut()>(group: &str, name: &str, size: usize, duration: Duration, run: F) -> Measurement {
+    let (iterations, elapsed_secs) = repeat(duration, run);
+    Measurement {
+        group: group.to_string(),
+        name: name.to_string(),
+        size: Some(size),
+        iterations,
+        elapsed_secs,
+        rate: (size as f64 * iterations as f64) / elapsed_secs / 1_000_000.0,
+        unit: "MB/s".to_string(),
+    }
+}
+
+/// measures the codecs and checksums over buffers of each of `sizes`
+/// and the key derivations, spending about `duration` on each
+pub fn run(sizes: &[usize], duration: Duration) -> Result<Vec<Measurement>, Error> {
+    let key = Aes256Key::new([0x47; 32], [0x54; 16], &[], 0);
+    let codec = Aes256CbcCodec::new_with_key(&key)?;
+    let ccs = ChaCha20Key::from_aeskey(&key)?;
+    let mut measurements = Vec::new();
+
+    for &size in sizes {
+        let plaintext = vec![0x35u8; size];
+        let ciphertext = codec.encrypt_blocks(&plaintext);
+        let sealed = seal(&key, &plaintext)?;
+        measurements.push(throughput("codec", "aes256cbc-encrypt", size, duration, || {
+            black_box(codec.encrypt_blocks(black_box(&plaintext)));
+        }));
+        measurements.push(throughput("codec", "aes256cbc-decrypt", size, duration, || {
+            black_box(codec.decrypt_blocks(black_box(&ciphertext)));
+        }));
+        let mut buffer = plaintext.clone();
+        measurements.push(throughput("codec", "chacha20", size, duration, || {
+            ccs.engine().apply_keystream(black_box(&mut buffer));
+        }));
+        measurements.push(throughput("codec", "seal", size, duration, || {
+            let _ = black_box(seal(&key, black_box(&plaintext)));
+        }));
+        measurements.push(throughput("codec", "unseal", size, duration, || {
+            let _ = black_box(unseal(&key, black_box(&sealed)));
+        }));
+        measurements.push(throughput("hash", "gcrc128", size, duration, || {
+            black_box(gcrc128(black_box(&plaintext)));
+        }));
+        measurements.push(throughput("hash", "gcrc256", size, duration, || {
+            black_box(gcrc256(black_box(&plaintext)));
+        }));
+        measurements.push(throughput("hash", "sha3_256", size, duration, || {
+            black_box(Sha3_256::digest(black_box(&plaintext)));
+        }));
+        measurements.push(throughput("hash", "sha3_512", size, duration, || {
+            black_box(Sha3_512::digest(black_box(&plaintext)));
+        }));
+    }
+
+    let probe = [0x35u8; 32];
+    for algo in [
+        Pbkdf2HashingAlgo::Sha3_256,
+        Pbkdf2HashingAlgo::Sha3_384,
+        Pbkdf2HashingAlgo::Sha3_512,
+    ] {
+        let scheme = DerivationScheme::Pbkdf2(algo);
+        let cycles = 0x400u32;
+        let (iterations, elapsed_secs) = repeat(duration, || {
+            black_box(scheme.derive(black_box(&probe), &probe, cycles));
+        });
+        measurements.push(Measurement {
+            group: "kdf".to_string(),
+            name: format!("{}", scheme),
+            size: None,
+            iterations,
+            elapsed_secs,
+            rate: (cycles as f64 * iterations as f64) / elapsed_secs,
+            unit: "cycles/s".to_string(),
+        });
+    }
+    Ok(measurements)
+}
+
+/// parses sizes such as `4096`, `64k` or `1m`
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    let value = value.trim().to_lowercase();
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') => (&value[..value.len() - 1], 0x400),
+        Some('m') => (&value[..value.len() - 1], 0x100000),
+        _ => (value.as_str(), 1),
+    };
+    match number.parse::<usize>().ok().and_then(|number| number.checked_mul(multiplier)) {
+        Some(size) if size > 0 => Ok(size),
+        _ => Err(format!("invalid size {:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use crate::bench::{parse_size, run};
+    use crate::errors::Error;
+    use k9::assert_equal;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_parse_size() {
+        assert_equal!(parse_size("4096"), Ok(4096));
+        assert_equal!(parse_size("64k"), Ok(0x10000));
+        assert_equal!(parse_size("1M"), Ok(0x100000));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size(&format!("{}m", usize::MAX)).is_err());
+    }
+
+    #[test]
+    pub fn test_run() -> Result<(), Error> {
+        let measurements = run(&[64], Duration::from_millis(1))?;
+        assert_equal!(measurements.len(), 12);
+        for measurement in measurements.iter() {
+            assert!(measurement.iterations > 0);
+            assert!(measurement.rate > 0.0);
+        }
+        Ok(())
+    }
+}
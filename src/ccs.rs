@@ -1,27 +1,31 @@
 use chacha20::cipher::KeyIvInit;
 use chacha20::ChaCha20;
 use crate::aescbc::cdc::Aes256Key;
-use crate::aescbc::tp::{B96, B256};
+use crate::aescbc::secret::SecretArray;
 use crate::errors::Error;
 use crate::aescbc::kd::pbkdf2_sha512;
 use crate::aescbc::kd::pbkdf2_sha384;
+use zeroize::Zeroizing;
 
+/// wiped when dropped, see [`crate::aescbc::secret`]
 pub struct ChaCha20Key {
-    key: B256,
-    nonce: B96,
+    key: SecretArray<32>,
+    nonce: SecretArray<12>,
 }
 
 impl ChaCha20Key {
     pub fn from_aeskey(ak: &Aes256Key) -> Result<ChaCha20Key, Error> {
-        let mut blob = Vec::<u8>::new();
-        blob.extend(&ak.siv());
-        blob.extend(&ak.skey());
+        let mut blob = Zeroizing::new(Vec::<u8>::new());
+        let skey = ak.skey()?;
+        let siv = ak.siv()?;
+        blob.extend(siv);
+        blob.extend(skey);
 
-        let p00 = pbkdf2_sha512(&blob, &ak.skey(), blob[blob.len()-1] as u32, 32);
-        let mut key: B256 = [0; 32];
+        let p00 = Zeroizing::new(pbkdf2_sha512(&blob, skey, blob[blob.len()-1] as u32, 32));
+        let mut key: SecretArray<32> = Zeroizing::new([0; 32]);
         key.copy_from_slice(&p00[p00.len()-32..]);
-        let p10 = pbkdf2_sha384(&blob, &ak.siv(), blob[blob.len()-1] as u32, 12);
-        let mut nonce: B96 = [0; 12];
+        let p10 = Zeroizing::new(pbkdf2_sha384(&blob, siv, blob[blob.len()-1] as u32, 12));
+        let mut nonce: SecretArray<12> = Zeroizing::new([0; 12]);
         nonce.copy_from_slice(&p10[p10.len()-12..]);
 
         Ok(ChaCha20Key {
@@ -30,6 +34,6 @@ impl ChaCha20Key {
         })
     }
     pub fn engine(&self) -> ChaCha20 {
-        ChaCha20::new(&self.key.into(), &self.nonce.into())
+        ChaCha20::new((&*self.key).into(), (&*self.nonce).into())
     }
 }
@@ -1,22 +1,79 @@
 // use clap_builder::derive::*;
-use crate::aescbc::Aes256Key;
-use crate::aescbc::DerivationScheme;
+use crate::aescbc::{key_name, Aes256Key};
+use crate::aescbc::{DerivationScheme, SecretBytes};
+use crate::aescbc::KeyFormat;
+use crate::aescbc::OpaqueDescriptor;
+use crate::bench::{parse_size, DEFAULT_SIZES};
+use crate::config::{config_path, Config, ConfigSetting, GeoConfig};
 use crate::errors::Error;
+use crate::ioutils::{absolute_path, read_bytes};
+use crate::output::OutputFormat;
+use crate::progress::{Bars, Progress};
+use crate::serial::FileFormat;
+use crate::prompt::read_secret;
+use crate::sources::SecretSource;
 // use crate::hashis::CrcAlgo;
-use crate::aescbc::config::Pbkdf2HashingAlgo;
+use crate::aescbc::config::{Pbkdf2Config, Pbkdf2HashingAlgo};
 // use atty::Stream;
 use clap::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::Rng;
 use std::io::{self, Read};
+use std::time::Duration;
 // use std::path::Path;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-#[command(propagate_version = true)]
+#[command(propagate_version = true, disable_version_flag = true, arg_required_else_help = true)]
 pub struct Cli {
     #[command(subcommand)]
-    pub command: Command,
+    pub command: Option<Command>,
+
+    #[arg(short = 'V', long, help = "print version")]
+    pub version: bool,
+
+    #[arg(long, requires = "version", help = "print the process protections in effect along with the version")]
+    pub verbose: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "skip process hardening and allow core dumps, swap and debuggers, for debugging obg itself"
+    )]
+    pub insecure_allow_dumps: bool,
+
+    #[arg(
+        long,
+        global = true,
+        default_value_t = OutputFormat::Text,
+        help = "json writes a single result object to STDOUT"
+    )]
+    pub output: OutputFormat,
+}
+
+impl Cli {
+    /// [`Parser::parse`] giving every subcommand the `--version` flag which
+    /// `obg` itself replaces in order to accept `--version --verbose`
+    pub fn parse_with_versions() -> Cli {
+        let matches = Cli::command_with_versions().get_matches();
+        Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
+    }
+    pub fn command_with_versions() -> clap::Command {
+        let mut command = Cli::command();
+        add_version_flags(&mut command);
+        command
+    }
+}
+
+fn add_version_flags(command: &mut clap::Command) {
+    for subcommand in command.get_subcommands_mut() {
+        let flag = Arg::new("version")
+            .short('V')
+            .long("version")
+            .action(ArgAction::Version)
+            .help("Print version");
+        *subcommand = std::mem::take(subcommand).arg(flag);
+        add_version_flags(subcommand);
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -40,12 +97,27 @@ pub trait KeyDeriver {
 
 #[derive(Args, Debug)]
 pub struct KeygenArgs {
-    #[arg(short = 'o', long)]
-    pub output_file: String,
+    #[arg(short = 'o', long, required_unless_present = "verify")]
+    pub output_file: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "KEY_FILE",
+        conflicts_with_all = ["output_file", "random", "x25519", "target_time"],
+        help = "re-derives the key from the given password and salt and fails unless it matches KEY_FILE"
+    )]
+    pub verify: Option<String>,
 
-    #[arg(short, long, help = "save yaml key")]
+    #[arg(
+        short,
+        long,
+        help = "saves the key as text in the format of --format, else of the extension of the output file, else yaml"
+    )]
     pub yaml: bool,
 
+    #[arg(short = 'F', long, help = "saves the key as yaml, json or toml, implies --yaml")]
+    pub format: Option<FileFormat>,
+
     #[arg(short, long)]
     pub force: bool,
 
@@ -83,8 +155,13 @@ pub struct KeygenArgs {
     )]
     pub salt_hwm: u64,
 
-    #[arg(short = 'D', long, env = "OBG_DS", default_value_t = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_384))]
-    pub salt_derivation_scheme: DerivationScheme,
+    #[arg(
+        short = 'D',
+        long,
+        env = "OBG_DS",
+        help = "defaults to the config or ds_pbkdf2_sha3_384"
+    )]
+    pub salt_derivation_scheme: Option<DerivationScheme>,
 
     #[arg(short, long)]
     pub quiet: bool,
@@ -94,9 +171,18 @@ pub struct KeygenArgs {
         long,
         requires_if("false", "interactive"),
         env = "OBG_PBDKF2_CYCLES",
-        default_value_t = 54325
+        help = "defaults to the config or 54325"
+    )]
+    pub cycles: Option<u64>,
+
+    #[arg(
+        short = 'T',
+        long,
+        conflicts_with = "cycles",
+        value_parser = parse_duration,
+        help = "picks the cycles for the derivation to last about this long on this machine, e.g. 1s or 500ms"
     )]
-    pub cycles: u64,
+    pub target_time: Option<Duration>,
 
     #[arg(
         short = 'r',
@@ -105,95 +191,185 @@ pub struct KeygenArgs {
     )]
     pub shuffle_iv: bool,
 
+    #[arg(
+        long,
+        conflicts_with = "random",
+        help = "derives the blob length and any shuffling of the IV from the password and salt such that the same inputs give the same key"
+    )]
+    pub deterministic: bool,
+
     #[arg(
         short = 'R',
         long = "random",
-        help = "generates key and iv with (pseudo-)random data rather in lieu of derivation"
+        help = "draws key, iv and blob from the OS random number generator in lieu of derivation"
     )]
     pub random: bool,
 
     #[arg(short, long, help = "whether to ask password interactively")]
     pub interactive: bool,
 
+    #[arg(
+        long,
+        env = "OBG_PINENTRY",
+        help = "pinentry program to ask passwords with, implies --interactive"
+    )]
+    pub pinentry: Option<String>,
+
+    /// password typed at the prompt, see [`KeygenArgs::ask_secrets`]
+    #[arg(skip)]
+    pub typed_password: Option<SecretBytes>,
+
+    /// salt typed at the prompt, see [`KeygenArgs::ask_secrets`]
+    #[arg(skip)]
+    pub typed_salt: Option<SecretBytes>,
+
     #[arg(short = 'l', long, env = "OBG_BL", help = "blob length")]
     pub blob_length: Option<u64>,
+
+    #[arg(
+        long,
+        help = "generates a x25519 identity in lieu of a key along with its recipient <output-file>.pub"
+    )]
+    pub x25519: bool,
 }
 
-impl KeyDeriver for KeygenArgs {
-    fn derive_key(&self, shuffle_iv: bool) -> Result<Aes256Key, Error> {
-        let password = if self.random {
-            let mut rng = rand::thread_rng();
-            let mut arr = Vec::<u8>::new();
-            arr.resize(self.password_hwm as usize, 0);
+pub const DEFAULT_CYCLES: u64 = 54325;
+pub const DEFAULT_DERIVATION_SCHEME: DerivationScheme = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_384);
 
-            if !self.quiet {
-                let pb = ProgressBar::new(self.cycles as u64)
-                    .with_message("Generating Password")
-                    .with_style(
-                        ProgressStyle::with_template("{msg} [{elapsed_precise}] {bar:71.220}")?
-                            .progress_chars("★ ✩"),
-                    );
-                for r in 0..self.cycles {
-                    pb.inc(r.into());
-                    rng.fill(&mut arr[..]);
-                }
-                pb.finish();
-            } else {
-                for _ in 0..self.cycles {
-                    rng.fill(&mut arr[..]);
-                }
-            }
+/// parses durations such as `1s`, `1.5s`, `500ms` or `2m`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: f64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => return Err(format!("invalid duration {:?}", value)),
+    };
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return Err(format!("unknown unit {:?} in duration {:?}", unit, value)),
+    };
+    if seconds <= 0.0 {
+        return Err(format!("duration must be positive, got {:?}", value));
+    }
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => Ok(duration),
+        Err(_) => Err(format!("duration {:?} is out of range", value)),
+    }
+}
 
-            vec![hex::encode(arr)]
-        } else {
-            self.password.clone()
-        };
-        let salt = if self.random {
-            let mut rng = rand::thread_rng();
-            let mut arr = Vec::<u8>::new();
-            arr.resize(self.salt_hwm as usize, 0);
+impl KeygenArgs {
+    /// fills the options which were not provided with those of `config`
+    pub fn configure(&mut self, config: &GeoConfig) {
+        if self.salt_derivation_scheme.is_none() {
+            self.salt_derivation_scheme = Some(config.password().derivation.clone());
+        }
+        if self.cycles.is_none() {
+            self.cycles = config.password().cycles();
+        }
+        self.shuffle_iv = self.shuffle_iv || config.iv().shuffle();
+    }
+    /// asks a password and a salt without echo when --interactive or
+    /// --pinentry are given, each entered twice and kept as bytes which
+    /// are wiped when dropped rather than as --password or --salt values
+    pub fn ask_secrets(&mut self) -> Result<(), Error> {
+        if !self.interactive && self.pinentry.is_none() {
+            return Ok(());
+        }
+        let pinentry = self.pinentry.as_deref();
+        self.typed_password = Some(read_secret("password", true, pinentry)?);
+        self.typed_salt = Some(read_secret("salt", true, pinentry)?);
+        Ok(())
+    }
+    /// replaces the cycles with those lasting --target-time
+    pub fn calibrate(&mut self) -> Result<(), Error> {
+        if let Some(target) = self.target_time {
+            let scheme = self.salt_derivation_scheme();
+            let cycles = scheme.calibrate(target)?;
             if !self.quiet {
-                let pb = ProgressBar::new(self.cycles as u64)
-                    .with_message("Factoring Salt")
-                    .with_style(ProgressStyle::with_template(
-                        "{msg}      [{elapsed_precise}] {bar:71.237}",
-                    )?);
-                for r in 0..self.cycles {
-                    pb.inc(r.into());
-                    rng.fill(&mut arr[..]);
-                }
-                pb.finish();
-            } else {
-                for _ in 0..self.cycles {
-                    rng.fill(&mut arr[..]);
-                }
+                eprintln!("calibrated {} cycles of {} to last {:?}", cycles, scheme, target);
             }
-            vec![hex::encode(arr)]
-        } else {
-            self.salt.clone()
-        };
+            self.cycles = Some(cycles);
+        }
+        Ok(())
+    }
+    /// the format of text keys, `None` for binary keys which --random
+    /// never writes since the binary layout cannot record its provenance
+    pub fn text_format(&self) -> Option<FileFormat> {
+        text_format(self.format, self.yaml || self.random, self.output_file())
+    }
+    pub fn output_file(&self) -> &str {
+        self.output_file.as_deref().unwrap_or_default()
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cycles.unwrap_or(DEFAULT_CYCLES)
+    }
+    pub fn salt_derivation_scheme(&self) -> DerivationScheme {
+        match &self.salt_derivation_scheme {
+            Some(scheme) => scheme.clone(),
+            None => DEFAULT_DERIVATION_SCHEME,
+        }
+    }
+}
 
-        if password.len() == 0 {
-            // let mut e = clap::error::Error::new(ErrorKind::MissingRequiredArgument);
-            // e.insert(clap::error::ContextKind::Custom, clap::error::ContextValue::String("at least one password is required".to_string()));
-            // return Err(e.into());
-            panic!("provide password or pass the --random flag");
-        } else {
-            for (index, sec) in password.iter().enumerate() {
-                if sec.len() == 0 {
-                    panic!("password at position {} is empty", index);
-                }
-            }
+impl KeygenArgs {
+    /// fails unless `key` derives from the password and salt given, see
+    /// [`Aes256Key::verify_derivation`]
+    pub fn verify_key(&self, key: &Aes256Key) -> Result<(), Error> {
+        let (passwords, salts) = (self.password_sources()?, self.salt_sources()?);
+        if passwords.is_empty() {
+            return Err(Error::EmptyPassword("provide the password to verify the key with".to_string()));
         }
-        if salt.len() == 0 {
-            panic!("provide salt or pass the --random flag");
-        } else {
-            for (index, st) in salt.iter().enumerate() {
-                if st.len() == 0 {
-                    panic!("salt at position {} is empty", index);
-                }
-            }
+        if salts.is_empty() {
+            return Err(Error::EmptySalt("provide the salt to verify the key with".to_string()));
         }
+        warn_ambiguous_sources("password", &self.password)?;
+        warn_ambiguous_sources("salt", &self.salt)?;
+        key.verify_derivation(
+            passwords,
+            self.password_hwm,
+            salts,
+            self.salt_hwm,
+            self.salt_derivation_scheme(),
+        )
+    }
+    /// the --password sources followed by the password typed at the prompt
+    pub fn password_sources(&self) -> Result<Vec<SecretSource>, Error> {
+        Ok(with_typed(SecretSource::parse_all(&self.password)?, &self.typed_password))
+    }
+    /// the --salt sources followed by the salt typed at the prompt
+    pub fn salt_sources(&self) -> Result<Vec<SecretSource>, Error> {
+        Ok(with_typed(SecretSource::parse_all(&self.salt)?, &self.typed_salt))
+    }
+}
+
+impl KeyDeriver for KeygenArgs {
+    fn derive_key(&self, shuffle_iv: bool) -> Result<Aes256Key, Error> {
+        if self.random {
+            return Aes256Key::random(self.blob_length)?.with_name(key_name(self.output_file()));
+        }
+        let (password, salt) = (self.password_sources()?, self.salt_sources()?);
+
+        if password.is_empty() {
+            return Err(Error::EmptyPassword(
+                "provide password or pass the --random flag".to_string(),
+            ));
+        }
+        if let Some(index) = self.password.iter().position(|sec| sec.is_empty()) {
+            return Err(Error::EmptyPassword(format!("password at position {} is empty", index)));
+        }
+        if salt.is_empty() {
+            return Err(Error::EmptySalt("provide salt or pass the --random flag".to_string()));
+        }
+        if let Some(index) = self.salt.iter().position(|st| st.is_empty()) {
+            return Err(Error::EmptySalt(format!("salt at position {} is empty", index)));
+        }
+        warn_ambiguous_sources("password", &self.password)?;
+        warn_ambiguous_sources("salt", &self.salt)?;
         if !self.quiet {
             let pb = ProgressBar::new(2)
                 .with_message("More Computation")
@@ -202,24 +378,40 @@ impl KeyDeriver for KeygenArgs {
                 )?);
             pb.inc(1);
         }
-        Aes256Key::derive_with_name(
-            &self.output_file,
-            password.clone(),
-            self.password_hwm,
-            salt.clone(),
-            self.salt_hwm,
-            self.cycles,
-            self.salt_derivation_scheme.clone(),
-            shuffle_iv,
+        Aes256Key::derive_from_sources(
+            password,
+            salt,
+            Pbkdf2Config::new(self.salt_derivation_scheme(), self.cycles(), shuffle_iv, self.deterministic)
+                .with_high_water_marks(self.password_hwm, self.salt_hwm),
             self.blob_length,
-        )
+        )?
+        .with_name(key_name(self.output_file()))
     }
 }
 
-#[derive(Args, Debug)]
+/// appends the secret typed at the prompt, if any, to `sources`
+fn with_typed(mut sources: Vec<SecretSource>, typed: &Option<SecretBytes>) -> Vec<SecretSource> {
+    if let Some(secret) = typed {
+        sources.push(SecretSource::Prompt(secret.clone()));
+    }
+    sources
+}
+
+/// warns on STDERR about each of `sources` which has no scheme, see
+/// [`SecretSource::ambiguity`]
+pub fn warn_ambiguous_sources(what: &str, sources: &[String]) -> Result<(), Error> {
+    for (index, source) in sources.iter().enumerate() {
+        if let Some(ambiguity) = SecretSource::parse(source)?.ambiguity() {
+            eprintln!("{} {} at position {} {}", console::style("Warning:").color256(214), what, index, ambiguity);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone)]
 #[group(multiple = true)]
 pub struct KeyOptions {
-    #[arg(short, long, required = false, env = "OBG_KEY_FILE")]
+    #[arg(short, long, required = false, default_value = "", env = "OBG_KEY_FILE")]
     //, overrides_with_all(["password", "salt"]))]
     pub key_file: String,
     // #[arg(short, long, required=false)]
@@ -258,14 +450,57 @@ pub struct KeyOptions {
     #[arg(short = 'm', long = "mo", env = "OBG_MO", help = "middle-out offset")]
     pub mo_offset: bool,
 }
+impl KeyOptions {
+    /// falls back to the key file of `config` when --key-file is not provided
+    pub fn configure(&mut self, config: &GeoConfig) {
+        if self.key_file.is_empty() {
+            if let Some(key_file) = config.key_file() {
+                self.key_file = absolute_path(&key_file);
+            }
+        }
+    }
+}
+impl KeyOptions {
+    /// picks the key file of the longest path in the config tree which
+    /// contains `path` unless --key-file was provided, returns how the
+    /// key was selected
+    pub fn select(&mut self, config: Option<&Config>, path: &str) -> String {
+        if !self.key_file.is_empty() {
+            return format!("--key-file {}", self.key_file);
+        }
+        let config = match config {
+            Some(config) => config,
+            None => return format!("no --key-file and no config at {}", config_path()),
+        };
+        match config.lookup(path) {
+            Some((rule, geo)) if geo.key_file().is_some() => {
+                self.configure(&geo);
+                format!("key {} of path {} which contains {}", self.key_file, rule, path)
+            }
+            _ => {
+                self.configure(config.get_default());
+                if self.key_file.is_empty() {
+                    format!("no path of {} contains {} and there is no default key", config_path(), path)
+                } else {
+                    format!("default key {} since no path contains {}", self.key_file, path)
+                }
+            }
+        }
+    }
+}
 impl KeyLoader for KeyOptions {
     fn load_key(&self) -> Result<Aes256Key, Error> {
+        if !self.opaque {
+            if let Ok(descriptor) = OpaqueDescriptor::load_from_config_file(self.key_file.clone()) {
+                return descriptor.load_key();
+            }
+        }
         match self.key_file.len() {
             0 => Err(Error::InvalidCliArg(format!(
                 "--key-file is required when --password is not provided"
             ))),
             _ => match self.opaque {
-                false => Ok(Aes256Key::load_from_yaml_file(
+                false => Ok(Aes256Key::load_from_config_file(
                     self.key_file.clone(),
                 ).unwrap_or(Aes256Key::load_from_file(
                     self.key_file.clone(),
@@ -324,15 +559,85 @@ pub struct EncryptFileParams {
     pub input_file: String,
     pub output_file: String,
 
+    #[arg(long, help = "explains which key was selected and why")]
+    pub explain: bool,
+
+    #[arg(
+        short = 'R',
+        long = "recipient",
+        help = "x25519 recipient file to which a random file key is wrapped, may be repeated"
+    )]
+    pub recipients: Vec<String>,
+
+    #[arg(
+        short = 'P',
+        long,
+        help = "derives the key from a password and a random salt stored in the header, may be repeated"
+    )]
+    pub password: Vec<String>,
+
+    #[arg(short = 'c', long, help = "password cycles, defaults to the config or 54325")]
+    pub cycles: Option<u64>,
+
+    #[arg(
+        short = 'D',
+        long,
+        help = "password derivation, defaults to the config or ds_pbkdf2_sha3_384"
+    )]
+    pub salt_derivation_scheme: Option<DerivationScheme>,
+
+    #[arg(short, long, help = "hides the progress bars of large inputs")]
+    pub quiet: bool,
+
     #[command(flatten)]
     pub key_opts: KeyOptions,
 }
+impl EncryptFileParams {
+    pub fn progress(&self) -> Result<Box<dyn Progress>, Error> {
+        Bars::for_file(&self.input_file, self.quiet)
+    }
+    /// the password given via --password or asked twice with --interactive
+    pub fn passphrase(&self) -> Result<Option<Vec<u8>>, Error> {
+        read_passphrase(&self.password, self.key_opts.interactive, true)
+    }
+    pub fn cycles(&self, config: Option<&GeoConfig>) -> u64 {
+        self.cycles
+            .or(config.and_then(|config| config.password().cycles()))
+            .unwrap_or(DEFAULT_CYCLES)
+    }
+    pub fn salt_derivation_scheme(&self, config: Option<&GeoConfig>) -> DerivationScheme {
+        match (&self.salt_derivation_scheme, config) {
+            (Some(scheme), _) => scheme.clone(),
+            (None, Some(config)) => config.password().derivation.clone(),
+            (None, None) => DEFAULT_DERIVATION_SCHEME,
+        }
+    }
+}
 impl KeyLoader for EncryptFileParams {
     fn load_key(&self) -> Result<Aes256Key, Error> {
         self.key_opts.load_key()
     }
 }
 
+pub const PASSPHRASE_HWM: u64 = 0x400;
+
+/// reads and concatenates passphrase `sources`, or asks it when
+/// `interactive`, `None` means no passphrase was requested
+pub fn read_passphrase(sources: &[String], interactive: bool, confirm: bool) -> Result<Option<Vec<u8>>, Error> {
+    if !sources.is_empty() {
+        warn_ambiguous_sources("password", sources)?;
+        let mut passphrase = Vec::<u8>::new();
+        for source in sources {
+            passphrase.extend(SecretSource::parse(source)?.read(PASSPHRASE_HWM)?);
+        }
+        Ok(Some(passphrase))
+    } else if interactive {
+        Ok(Some(read_secret("password", confirm, None)?.expose().to_vec()))
+    } else {
+        Ok(None)
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct DecryptTextParams {
     pub ciphertext: Option<String>,
@@ -369,16 +674,333 @@ pub struct DecryptFileParams {
     pub input_file: String,
     pub output_file: String,
 
+    #[arg(long, help = "explains which key was selected and why")]
+    pub explain: bool,
+
+    #[arg(short = 'I', long, help = "x25519 identity file which unwraps the file key")]
+    pub identity: Option<String>,
+
+    #[arg(
+        short = 'P',
+        long,
+        help = "password the file was encrypted with, may be repeated"
+    )]
+    pub password: Vec<String>,
+
+    #[arg(short, long, help = "hides the progress bars of large inputs")]
+    pub quiet: bool,
+
     #[command(flatten)]
     pub key_opts: KeyOptions,
 }
+impl DecryptFileParams {
+    pub fn progress(&self) -> Result<Box<dyn Progress>, Error> {
+        Bars::for_file(&self.input_file, self.quiet)
+    }
+    /// the password given via --password or asked with --interactive
+    pub fn passphrase(&self) -> Result<Option<Vec<u8>>, Error> {
+        read_passphrase(&self.password, self.key_opts.interactive, false)
+    }
+}
 impl KeyLoader for DecryptFileParams {
     fn load_key(&self) -> Result<Aes256Key, Error> {
         self.key_opts.load_key()
     }
 }
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    #[arg(required = true)]
+    pub files: Vec<String>,
+
+    #[arg(short = 'I', long, help = "x25519 identity file which unwraps the file keys")]
+    pub identity: Option<String>,
+
+    #[arg(
+        short = 'P',
+        long,
+        help = "password the files were encrypted with, may be repeated"
+    )]
+    pub password: Vec<String>,
+
+    #[command(flatten)]
+    pub key_opts: KeyOptions,
+}
+impl VerifyArgs {
+    /// the password given via --password or asked with --interactive
+    pub fn passphrase(&self) -> Result<Option<Vec<u8>>, Error> {
+        read_passphrase(&self.password, self.key_opts.interactive, false)
+    }
+}
+impl KeyLoader for VerifyArgs {
+    fn load_key(&self) -> Result<Aes256Key, Error> {
+        self.key_opts.load_key()
+    }
+}
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+    #[command(about = "splits a key into shares of which a threshold is needed to recover it")]
+    Split(KeySplitArgs),
+    #[command(about = "recovers a key from its shares")]
+    Combine(KeyCombineArgs),
+    #[command(about = "writes a key in another encoding")]
+    Export(KeyExportArgs),
+    #[command(about = "builds a key out of externally generated material")]
+    Import(KeyImportArgs),
+    #[command(about = "records the carrier file and offsets of an opaque key in a yaml, json or toml descriptor")]
+    Describe(KeyDescribeArgs),
+}
+
+/// the format of text keys given --format and --yaml, else of the
+/// extension of `output_file`, else yaml; `None` for binary keys
+fn text_format(format: Option<FileFormat>, text: bool, output_file: &str) -> Option<FileFormat> {
+    match (format, text) {
+        (Some(format), _) => Some(format),
+        (None, true) => Some(FileFormat::from_extension(output_file).unwrap_or(FileFormat::Yaml)),
+        (None, false) => None,
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KeySplitArgs {
+    #[arg(short, long, env = "OBG_KEY_FILE")]
+    pub key_file: String,
+
+    #[arg(short = 'n', long, help = "amount of shares to generate")]
+    pub shares: u8,
+
+    #[arg(short, long, help = "amount of shares required to recover the key")]
+    pub threshold: u8,
+
+    #[arg(
+        short = 'o',
+        long,
+        help = "shares are saved as <output-prefix>.<index>.yml, or .json or .toml as given by --format"
+    )]
+    pub output_prefix: String,
+
+    #[arg(short = 'F', long, default_value_t = FileFormat::Yaml, help = "saves the shares as yaml, json or toml")]
+    pub format: FileFormat,
+
+    #[arg(short, long)]
+    pub force: bool,
+}
+impl KeySplitArgs {
+    pub fn share_file(&self, index: u8) -> String {
+        format!("{}.{}.{}", self.output_prefix, index, self.format.extension())
+    }
+}
+impl KeyLoader for KeySplitArgs {
+    fn load_key(&self) -> Result<Aes256Key, Error> {
+        Aes256Key::load(self.key_file.clone())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KeyCombineArgs {
+    #[arg(required = true)]
+    pub shares: Vec<String>,
+
+    #[arg(short = 'o', long)]
+    pub output_file: String,
+
+    #[arg(
+        short,
+        long,
+        help = "saves the key as text in the format of --format, else of the extension of the output file, else yaml"
+    )]
+    pub yaml: bool,
+
+    #[arg(short = 'F', long, help = "saves the key as yaml, json or toml, implies --yaml")]
+    pub format: Option<FileFormat>,
+
+    #[arg(short, long)]
+    pub force: bool,
+}
+impl KeyCombineArgs {
+    pub fn text_format(&self) -> Option<FileFormat> {
+        text_format(self.format, self.yaml, &self.output_file)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KeyExportArgs {
+    #[arg(short, long, env = "OBG_KEY_FILE")]
+    pub key_file: String,
+
+    #[arg(short = 'F', long, default_value_t = KeyFormat::Yaml)]
+    pub format: KeyFormat,
+
+    #[arg(short = 'o', long, help = "defaults to STDOUT")]
+    pub output_file: Option<String>,
+
+    #[arg(short, long)]
+    pub force: bool,
+}
+impl KeyLoader for KeyExportArgs {
+    fn load_key(&self) -> Result<Aes256Key, Error> {
+        Aes256Key::load(self.key_file.clone())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KeyImportArgs {
+    #[arg(help = "defaults to STDIN")]
+    pub input_file: Option<String>,
+
+    #[arg(short = 'F', long, default_value_t = KeyFormat::Hex)]
+    pub format: KeyFormat,
+
+    #[arg(short = 'o', long)]
+    pub output_file: String,
+
+    #[arg(
+        short,
+        long,
+        help = "saves the key as text in the format of --text-format, else of the extension of the output file, else yaml"
+    )]
+    pub yaml: bool,
+
+    #[arg(short = 'T', long, help = "saves the key as yaml, json or toml, implies --yaml")]
+    pub text_format: Option<FileFormat>,
+
+    #[arg(short, long)]
+    pub force: bool,
+}
+impl KeyImportArgs {
+    pub fn text_format(&self) -> Option<FileFormat> {
+        text_format(self.text_format, self.yaml, &self.output_file)
+    }
+    pub fn load_material(&self) -> Result<Vec<u8>, Error> {
+        match &self.input_file {
+            None => {
+                let mut buffer = Vec::new();
+                io::stdin().read_to_end(&mut buffer)?;
+                Ok(buffer)
+            }
+            Some(path) => read_bytes(&absolute_path(path)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KeyDescribeArgs {
+    #[arg(help = "file the opaque key is carved from")]
+    pub carrier: String,
+
+    #[arg(short = 'o', long, help = "descriptor to pass as --key-file")]
+    pub output_file: String,
+
+    #[arg(long, env = "OBG_KO", help = "key offset")]
+    pub key_offset: Option<usize>,
+
+    #[arg(long, env = "OBG_SO", help = "salt offset")]
+    pub salt_offset: Option<usize>,
+
+    #[arg(long, env = "OBG_BO", help = "blob offset")]
+    pub blob_offset: Option<usize>,
+
+    #[arg(long = "mo", env = "OBG_MO", help = "middle-out offset")]
+    pub mo_offset: bool,
+
+    #[arg(
+        short = 'F',
+        long,
+        help = "saves the descriptor as yaml, json or toml, defaults to the extension of the output file, else yaml"
+    )]
+    pub format: Option<FileFormat>,
+
+    #[arg(short, long)]
+    pub force: bool,
+}
+impl KeyDescribeArgs {
+    pub fn text_format(&self) -> FileFormat {
+        text_format(self.format, true, &self.output_file).unwrap_or(FileFormat::Yaml)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    #[command(about = "writes the default config")]
+    Init(ConfigInitArgs),
+    #[command(about = "prints the config")]
+    Show(ConfigShowArgs),
+    #[command(about = "changes a setting of the default config or of a path's config")]
+    Set(ConfigSetArgs),
+    #[command(about = "adds a config which applies to the given path")]
+    AddPath(ConfigAddPathArgs),
+    #[command(about = "removes the config of the given path")]
+    RemovePath(ConfigRemovePathArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigInitArgs {
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigShowArgs {
+    #[arg(short = 'F', long, help = "defaults to the format of the config file")]
+    pub format: Option<FileFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigSetArgs {
+    pub setting: ConfigSetting,
+    pub value: String,
+
+    #[arg(short, long, help = "path previously added via add-path")]
+    pub path: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigAddPathArgs {
+    pub path: String,
+
+    #[arg(short, long)]
+    pub key_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigRemovePathArgs {
+    pub path: String,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    #[arg(
+        short,
+        long = "size",
+        value_parser = parse_size,
+        help = "buffer size such as 4096, 64k or 1m, may be repeated, defaults to 1k, 64k and 1m"
+    )]
+    pub sizes: Vec<usize>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_duration,
+        default_value = "250ms",
+        help = "time spent measuring each codec, checksum and derivation"
+    )]
+    pub duration: Duration,
+
+    #[arg(short, long, hide = true, help = "deprecated alias of --output json")]
+    pub json: bool,
+}
+impl BenchArgs {
+    pub fn sizes(&self) -> Vec<usize> {
+        if self.sizes.is_empty() {
+            DEFAULT_SIZES.to_vec()
+        } else {
+            self.sizes.clone()
+        }
+    }
+}
+
 #[derive(Args, Debug)]
 pub struct IdOps {
+    #[arg(required = true)]
     pub filenames: Vec<String>,
 }
 
@@ -393,6 +1015,132 @@ pub enum Command {
     Encrypt(Encrypt),
     #[command(subcommand, about = "decrypts file or input ciphertext")]
     Decrypt(Decrypt),
-    #[command(about = "ascertain file's encrypted")]
+    #[command(about = "decrypts files in memory to prove they are intact, without writing plaintext")]
+    Verify(VerifyArgs),
+    #[command(about = "tells whether files are encrypted, keys or plain, exits with 0, 10 or 11 respectively")]
     Id(IdOps),
+    #[command(subcommand, about = "manages existing keys")]
+    Key(KeyCommand),
+    #[command(subcommand, about = "manages the config file at $OBG_CONFIG or ~/.config/obg/config.yaml")]
+    Config(ConfigCommand),
+    #[command(about = "measures the throughput of codecs, key derivations and checksums")]
+    Bench(BenchArgs),
+}
+impl Command {
+    /// name of the subcommand as typed on the command-line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Keygen(_) => "keygen",
+            Command::Encrypt(Encrypt::Text(_)) => "encrypt text",
+            Command::Encrypt(Encrypt::File(_)) => "encrypt file",
+            Command::Decrypt(Decrypt::Text(_)) => "decrypt text",
+            Command::Decrypt(Decrypt::File(_)) => "decrypt file",
+            Command::Id(_) => "id",
+            Command::Verify(_) => "verify",
+            Command::Key(KeyCommand::Split(_)) => "key split",
+            Command::Key(KeyCommand::Combine(_)) => "key combine",
+            Command::Key(KeyCommand::Export(_)) => "key export",
+            Command::Key(KeyCommand::Import(_)) => "key import",
+            Command::Key(KeyCommand::Describe(_)) => "key describe",
+            Command::Config(ConfigCommand::Init(_)) => "config init",
+            Command::Config(ConfigCommand::Show(_)) => "config show",
+            Command::Config(ConfigCommand::Set(_)) => "config set",
+            Command::Config(ConfigCommand::AddPath(_)) => "config add-path",
+            Command::Config(ConfigCommand::RemovePath(_)) => "config remove-path",
+            Command::Bench(_) => "bench",
+        }
+    }
+}
+
+#[cfg(test)]
+mod clap_tests {
+    use crate::aescbc::SecretBytes;
+    use crate::clap::{parse_duration, Cli, Command, KeyCommand, KeyDeriver};
+    use crate::errors::Error;
+    use crate::serial::FileFormat;
+    use clap::error::ErrorKind;
+    use clap::Parser;
+    use k9::assert_equal;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_typed_secrets() -> Result<(), Error> {
+        let args = ["obg", "keygen", "-o", "typed.yml", "-c", "3"];
+        let mut typed = match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Keygen(args)) => args,
+            _ => unreachable!(),
+        };
+        typed.typed_password = Some(SecretBytes::from(&b"correct horse"[..]));
+        typed.typed_salt = Some(SecretBytes::from(&b"battery staple"[..]));
+        assert!(!format!("{:?}", typed).contains("horse"));
+        let mut literal = match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Keygen(args)) => args,
+            _ => unreachable!(),
+        };
+        literal.password.push("literal:correct horse".to_string());
+        literal.salt.push("literal:battery staple".to_string());
+        let (typed, literal) = (typed.derive_key(false)?, literal.derive_key(false)?);
+        assert_equal!(typed.skey()?, literal.skey()?);
+        assert!(serde_yaml::to_string(&typed)?.contains("kind: prompt"));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_key_text_formats() {
+        let key_command = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Key(command)) => command,
+            _ => unreachable!(),
+        };
+        match key_command(&["obg", "key", "combine", "a.1.yml", "-o", "key.json", "--yaml"]) {
+            KeyCommand::Combine(args) => {
+                assert_equal!(args.text_format(), Some(FileFormat::Json));
+            }
+            _ => unreachable!(),
+        }
+        match key_command(&["obg", "key", "combine", "a.1.yml", "-o", "key.kgz"]) {
+            KeyCommand::Combine(args) => {
+                assert_equal!(args.text_format(), None);
+            }
+            _ => unreachable!(),
+        }
+        match key_command(&["obg", "key", "import", "-o", "key.kgz", "-T", "toml"]) {
+            KeyCommand::Import(args) => {
+                assert_equal!(args.text_format(), Some(FileFormat::Toml));
+            }
+            _ => unreachable!(),
+        }
+        match key_command(&["obg", "key", "describe", "nothing.png", "-o", "nothing.toml"]) {
+            KeyCommand::Describe(args) => {
+                assert_equal!(args.text_format(), FileFormat::Toml);
+            }
+            _ => unreachable!(),
+        }
+        match key_command(&["obg", "key", "split", "-k", "key.yml", "-n", "3", "-t", "2", "-o", "bg", "-F", "json"]) {
+            KeyCommand::Split(args) => {
+                assert_equal!(args.share_file(2), "bg.2.json");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_parse_duration() {
+        assert_equal!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_equal!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_equal!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_equal!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration(&"9".repeat(400)).is_err());
+    }
+
+    #[test]
+    pub fn test_version_flags() {
+        for args in [vec!["obg", "keygen", "--version"], vec!["obg", "key", "export", "-V"]] {
+            let error = Cli::command_with_versions().try_get_matches_from(args).unwrap_err();
+            assert_equal!(error.kind(), ErrorKind::DisplayVersion);
+        }
+        let matches = Cli::command_with_versions().try_get_matches_from(["obg", "--version", "--verbose"]);
+        assert!(matches.is_ok());
+    }
 }
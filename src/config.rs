@@ -1,16 +1,21 @@
+use crate::clap::{DEFAULT_CYCLES, DEFAULT_DERIVATION_SCHEME};
 use crate::errors::Error;
-use crate::serial::YamlFile;
+use crate::serial::ConfigFile;
 // use crate::aescbc::config::Pbkdf2Config;
 use crate::aescbc::kd::DerivationScheme;
-use crate::ioutils::{absolute_path, resolved_path};
+use crate::ioutils::{absolute_path, canonical_path, file_exists, resolved_path};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct PasswordConfig {
     pub derivation: DerivationScheme,
     source: Vec<String>, // vector of strings or files
     source_hwm: u64,     // source high water mark
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cycles: Option<u64>,
 }
 
 impl PasswordConfig {
@@ -35,13 +40,28 @@ impl PasswordConfig {
         self.source_hwm = high_water_mark;
         Ok(self.source_hwm != current)
     }
+    pub fn set_cycles(&mut self, cycles: Option<u64>) -> bool {
+        let current = self.cycles;
+        self.cycles = cycles;
+        self.cycles != current
+    }
+    pub fn source(&self) -> Vec<String> {
+        self.source.clone()
+    }
+    pub fn source_hwm(&self) -> u64 {
+        self.source_hwm
+    }
+    pub fn cycles(&self) -> Option<u64> {
+        self.cycles
+    }
 }
-impl YamlFile for PasswordConfig {
+impl ConfigFile for PasswordConfig {
     fn default() -> Result<PasswordConfig, Error> {
         Ok(PasswordConfig {
-            derivation: DerivationScheme::default()?,
+            derivation: DEFAULT_DERIVATION_SCHEME,
             source: vec!["/dev/random".into()],
             source_hwm: 1024 * 1024,
+            cycles: Some(DEFAULT_CYCLES),
         })
     }
 }
@@ -54,7 +74,18 @@ pub struct IVConfig {
     source_hwm: u64,     // source high water mark
 }
 
-impl YamlFile for IVConfig {
+impl IVConfig {
+    pub fn set_shuffle(&mut self, shuffle: bool) -> bool {
+        let current = self.shuffle;
+        self.shuffle = shuffle;
+        self.shuffle != current
+    }
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+}
+
+impl ConfigFile for IVConfig {
     fn default() -> Result<IVConfig, Error> {
         Ok(IVConfig {
             derivation: DerivationScheme::default()?,
@@ -70,6 +101,8 @@ pub struct GeoConfig {
     password: PasswordConfig,
     iv: IVConfig,
     path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_file: Option<String>,
 }
 
 impl GeoConfig {
@@ -91,14 +124,29 @@ impl GeoConfig {
         self.iv = config;
         self.iv != current
     }
+    pub fn set_key_file(&mut self, key_file: Option<String>) -> bool {
+        let current = self.key_file.clone();
+        self.key_file = key_file;
+        self.key_file != current
+    }
+    pub fn password(&self) -> &PasswordConfig {
+        &self.password
+    }
+    pub fn iv(&self) -> &IVConfig {
+        &self.iv
+    }
+    pub fn key_file(&self) -> Option<String> {
+        self.key_file.clone()
+    }
 }
 
-impl YamlFile for GeoConfig {
+impl ConfigFile for GeoConfig {
     fn default() -> Result<GeoConfig, Error> {
         Ok(GeoConfig {
             password: PasswordConfig::default()?,
             iv: IVConfig::default()?,
             path: absolute_path(".").into(),
+            key_file: None,
         })
     }
 }
@@ -112,7 +160,12 @@ impl Config {
     pub fn set_default(&mut self, config: GeoConfig) {
         self.default = config;
     }
-    pub fn add_path(&mut self, path: &str, config: GeoConfig) {
+    /// adds `config` for the tree of `path`, both `path` and the key file of
+    /// `config` are made absolute such that they do not depend on the
+    /// directory obg runs from
+    pub fn add_path(&mut self, path: &str, mut config: GeoConfig) {
+        config.path = resolved_path(path);
+        config.key_file = config.key_file.as_deref().map(resolved_path);
         match &mut self.tree {
             None => {
                 let mut tree = BTreeMap::new();
@@ -129,17 +182,121 @@ impl Config {
         match &mut self.tree {
             None => {
                 let mut tree = BTreeMap::new();
-                tree.remove(path);
+                tree.remove(&resolved_path(path));
                 self.tree = Some(tree);
             }
             Some(ref mut tree) => {
-                tree.remove(path);
+                tree.remove(&resolved_path(path));
                 self.tree = Some(tree.clone());
             }
         };
     }
+    pub fn get_default(&self) -> &GeoConfig {
+        &self.default
+    }
+    pub fn get_default_mut(&mut self) -> &mut GeoConfig {
+        &mut self.default
+    }
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut GeoConfig> {
+        match &mut self.tree {
+            None => None,
+            Some(tree) => tree.get_mut(&resolved_path(path)),
+        }
+    }
+    pub fn tree(&self) -> BTreeMap<String, GeoConfig> {
+        self.tree.clone().unwrap_or_default()
+    }
+    /// the longest path of the tree which contains `path` along with its config
+    pub fn lookup(&self, path: &str) -> Option<(String, GeoConfig)> {
+        let target = PathBuf::from(canonical_path(path));
+        self.tree()
+            .into_iter()
+            .filter(|(rule, _)| target.starts_with(absolute_path(rule)))
+            .max_by_key(|(rule, _)| Path::new(&absolute_path(rule)).components().count())
+    }
+    /// loads the config file at `path` if it exists
+    pub fn load(path: &str) -> Result<Option<Config>, Error> {
+        if file_exists(&absolute_path(path)) {
+            Ok(Some(Config::import(path)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ConfigSetting {
+    /// derivation scheme of keygen, e.g. pbkdf2_sha3_512
+    Derivation,
+    /// derivation cycles of keygen
+    Cycles,
+    /// key file used when --key-file is not provided
+    KeyFile,
+    /// high-water-mark of password sources
+    SourceHwm,
+    /// whether keygen shuffles the iv
+    ShuffleIv,
+}
+
+impl GeoConfig {
+    /// parses `value` and applies it to `setting`, returns whether it changed
+    pub fn apply(&mut self, setting: &ConfigSetting, value: &str) -> Result<bool, Error> {
+        Ok(match setting {
+            ConfigSetting::Derivation => {
+                let scheme = match <DerivationScheme as ValueEnum>::from_str(
+                    value.trim_start_matches("ds_"),
+                    true,
+                ) {
+                    Ok(scheme) => scheme,
+                    Err(e) => {
+                        return Err(Error::InvalidConfig(format!(
+                            "unknown derivation scheme {}",
+                            e
+                        )))
+                    }
+                };
+                self.password.set_derivation_scheme(scheme)
+            }
+            ConfigSetting::Cycles => self.password.set_cycles(match value {
+                "" | "none" => None,
+                value => Some(value.parse::<u64>()?),
+            }),
+            ConfigSetting::KeyFile => self.set_key_file(match value {
+                "" | "none" => None,
+                value => Some(value.to_string()),
+            }),
+            ConfigSetting::SourceHwm => self.password.set_source_hwm(value.parse::<u64>()?)?,
+            ConfigSetting::ShuffleIv => self.iv.set_shuffle(match value {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                value => {
+                    return Err(Error::InvalidConfig(format!(
+                        "expected true or false, got {}",
+                        value
+                    )))
+                }
+            }),
+        })
+    }
+}
+
+/// `$OBG_CONFIG` or else the first of `config.yaml`, `config.toml` and
+/// `config.json` which exists in `~/.config/obg`, `config.yaml` if none does
+pub fn config_path() -> String {
+    match std::env::var("OBG_CONFIG") {
+        Ok(path) if !path.is_empty() => absolute_path(&path),
+        _ => {
+            for name in ["config.yaml", "config.toml", "config.json"] {
+                let path = absolute_path(&format!("~/.config/obg/{}", name));
+                if file_exists(&path) {
+                    return path;
+                }
+            }
+            absolute_path("~/.config/obg/config.yaml")
+        }
+    }
 }
-impl YamlFile for Config {
+impl ConfigFile for Config {
     fn default() -> Result<Config, Error> {
         Ok(Config {
             default: GeoConfig::default()?,
@@ -150,19 +307,26 @@ impl YamlFile for Config {
 
 #[cfg(test)]
 mod config_tests {
+    use crate::aescbc::config::Pbkdf2HashingAlgo;
+    use crate::aescbc::kd::DerivationScheme;
+    use crate::clap::{Cli, Command, KeyDeriver, KeygenArgs};
     use crate::config::Config;
+    use crate::config::{ConfigSetting, GeoConfig};
     use crate::errors::Error;
-    use crate::serial::YamlFile;
+    use crate::ioutils::absolute_path;
+    use crate::serial::ConfigFile;
+    use clap::Parser;
     use k9::assert_equal;
 
     #[test]
     pub fn test_default_config_to_yaml() -> Result<(), Error> {
         let valid_yaml = "default:
   password:
-    derivation: pbkdf2_sha3_512
+    derivation: pbkdf2_sha3_384
     source:
     - /dev/random
     source_hwm: 1048576
+    cycles: 54325
   iv:
     derivation: pbkdf2_sha3_512
     shuffle: false
@@ -178,4 +342,82 @@ tree: {}
         assert_equal!(Config::from_yaml(valid_yaml)?, Config::default()?);
         Ok(())
     }
+
+    #[test]
+    pub fn test_default_config_derives_same_key() -> Result<(), Error> {
+        let keygen = || -> KeygenArgs {
+            match Cli::parse_from(["obg", "keygen", "-q", "-p", "literal:x", "-s", "literal:y", "-o", "k.yml"]).command {
+                Some(Command::Keygen(args)) => args,
+                _ => unreachable!(),
+            }
+        };
+        let unconfigured = keygen();
+        let mut configured = keygen();
+        configured.configure(&GeoConfig::default()?);
+        assert_equal!(configured.cycles(), unconfigured.cycles());
+        assert_equal!(configured.salt_derivation_scheme(), unconfigured.salt_derivation_scheme());
+        assert_equal!(configured.shuffle_iv, unconfigured.shuffle_iv);
+
+        let key = unconfigured.derive_key(unconfigured.shuffle_iv)?;
+        let configured_key = configured.derive_key(configured.shuffle_iv)?;
+        assert_equal!(configured_key.skey()?, key.skey()?);
+        assert_equal!(configured_key.siv()?, key.siv()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_apply_settings() -> Result<(), Error> {
+        let mut config = GeoConfig::default()?;
+        assert_equal!(config.apply(&ConfigSetting::Derivation, "ds_pbkdf2_sha3_256")?, true);
+        assert_equal!(
+            config.password().derivation,
+            DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256)
+        );
+        assert_equal!(config.apply(&ConfigSetting::Cycles, "84000")?, true);
+        assert_equal!(config.password().cycles(), Some(84000));
+        assert_equal!(config.apply(&ConfigSetting::KeyFile, "~/key.kgz")?, true);
+        assert_equal!(config.key_file(), Some("~/key.kgz".to_string()));
+        assert!(config.apply(&ConfigSetting::SourceHwm, "31").is_err());
+        assert!(config.to_yaml()?.contains("cycles: 84000"));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_lookup_longest_prefix() -> Result<(), Error> {
+        let mut config = Config::default()?;
+        let mut outer = GeoConfig::default()?;
+        outer.set_key_file(Some("/srv/keys/outer.kgz".to_string()));
+        let mut inner = GeoConfig::default()?;
+        inner.set_key_file(Some("/srv/keys/inner.kgz".to_string()));
+        config.add_path("/srv/projects", outer);
+        config.add_path("/srv/projects/secret", inner);
+
+        let (rule, geo) = config.lookup("/srv/projects/secret/notes.txt").unwrap();
+        assert_equal!(rule, "/srv/projects/secret".to_string());
+        assert_equal!(geo.key_file(), Some("/srv/keys/inner.kgz".to_string()));
+
+        let (rule, _) = config.lookup("/srv/projects/secretive/notes.txt").unwrap();
+        assert_equal!(rule, "/srv/projects".to_string());
+        assert!(config.lookup("/srv/elsewhere/notes.txt").is_none());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_lookup_relative_path() -> Result<(), Error> {
+        let mut config = Config::default()?;
+        let mut geo = GeoConfig::default()?;
+        geo.set_key_file(Some("tests/tree.yml".to_string()));
+        config.add_path("tests", geo);
+
+        let current = std::env::current_dir()?;
+        let (rule, geo) = config.lookup("tests/sub/a.txt").unwrap();
+        assert_equal!(absolute_path(&rule), format!("{}", current.join("tests").display()));
+        assert_equal!(
+            absolute_path(&geo.key_file().unwrap()),
+            format!("{}", current.join("tests/tree.yml").display())
+        );
+        assert!(config.lookup(&format!("{}/tests/a.txt", current.display())).is_some());
+        assert!(config.lookup("src/main.rs").is_none());
+        Ok(())
+    }
 }
@@ -0,0 +1,421 @@
+/*!
+Format Detection
+
+Tells apart the files written by `obg`: each encrypted container, keys
+in the binary, yaml and exported layouts, x25519 identities, recipients
+and key shares, hex ciphertexts from `obg encrypt text` and everything
+else, which is considered plain.
+*/
+use crate::aescbc::cdc::{match_prefix, Aes256Key};
+use crate::aescbc::descriptor::OpaqueDescriptor;
+use crate::aescbc::ie::{import_key, KeyFormat};
+use crate::envelope::{X25519Identity, X25519Recipient, WRAPPED_KEY_WIDTH};
+use crate::errors::{exit, Error};
+use crate::pap::TAG_WIDTH;
+use crate::passphrase::PassphraseHeader;
+use crate::serial::{self, FileFormat};
+use crate::shamir::KeyShare;
+use crate::sneaker::core;
+use crate::sneaker::io::read_start;
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// textual formats are only recognized in files up to this size
+pub const SNIFF_LIMIT: u64 = 0x10000;
+
+/// offset of the byte which tells the container versions apart
+const CONTAINER_OFFSET: usize = 7;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    Encrypted,
+    Key,
+    Plain,
+    Unreadable,
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Class::Encrypted => write!(f, "encrypted"),
+            Class::Key => write!(f, "key"),
+            Class::Plain => write!(f, "plain"),
+            Class::Unreadable => write!(f, "unreadable"),
+        }
+    }
+}
+
+impl Class {
+    /// exit code of `obg id`, see [`crate::errors::exit`]
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Class::Encrypted => exit::SUCCESS,
+            Class::Key => exit::ID_KEY,
+            Class::Plain => exit::ID_PLAIN,
+            Class::Unreadable => exit::IO,
+        }
+    }
+}
+
+/// unreadable wins over plain which wins over key which wins over
+/// encrypted, so that success means every file is encrypted
+pub fn exit_code(detections: &[Detection]) -> u8 {
+    let class = detections.iter().map(|detection| detection.class).fold(
+        Class::Encrypted,
+        |worst, class| match (worst, class) {
+            (Class::Unreadable, _) | (_, Class::Unreadable) => Class::Unreadable,
+            (Class::Plain, _) | (_, Class::Plain) => Class::Plain,
+            (Class::Key, _) | (_, Class::Key) => Class::Key,
+            _ => Class::Encrypted,
+        },
+    );
+    class.exit_code()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Detection {
+    pub path: String,
+    pub class: Class,
+    pub format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    pub bytes: u64,
+}
+
+impl Detection {
+    fn new(class: Class, format: &str, details: Option<String>) -> Detection {
+        Detection {
+            path: String::new(),
+            class,
+            format: format.to_string(),
+            details,
+            bytes: 0,
+        }
+    }
+}
+
+/// the detection of a file which could not be read, carrying the error
+pub fn unreadable(path: &str, error: &Error) -> Detection {
+    let mut detection = Detection::new(Class::Unreadable, error.root().kind(), Some(error.report()));
+    detection.path = path.to_string();
+    detection
+}
+
+pub fn detect_file(path: &str) -> Result<Detection, Error> {
+    let mut file = File::open(path)?;
+    let mut detection = detect(&mut file)?;
+    detection.path = path.to_string();
+    Ok(detection)
+}
+
+/// detects the format of `source`, reading at most [`SNIFF_LIMIT`] bytes
+/// unless a container header says otherwise
+pub fn detect<S: Read + Seek>(source: &mut S) -> Result<Detection, Error> {
+    let head = read_start(source, SNIFF_LIMIT as usize)?;
+    let bytes = source.seek(SeekFrom::End(0))?;
+    let mut detection = match detect_container(source, &head, bytes)? {
+        Some(detection) => detection,
+        None if head.len() >= 8 && match_prefix(&head[..8]) => {
+            match Aes256Key::from_bytes(head.clone(), true) {
+                Ok(key) => Detection::new(
+                    Class::Key,
+                    "obg-binary-key",
+                    Some(match key.cycles {
+                        Some(cycles) => format!("{}, {} cycles", key.version, cycles),
+                        None => key.version,
+                    }),
+                ),
+                Err(e) => Detection::new(Class::Key, "obg-binary-key", Some(format!("corrupted: {}", e))),
+            }
+        }
+        None if bytes <= SNIFF_LIMIT => detect_text(&head),
+        None => Detection::new(Class::Plain, "data", None),
+    };
+    detection.bytes = bytes;
+    Ok(detection)
+}
+
+/// containers start with [`core::magic_id`] in which the byte at
+/// [`CONTAINER_OFFSET`] tells how the file key is obtained
+fn detect_container<S: Read + Seek>(
+    source: &mut S,
+    head: &[u8],
+    bytes: u64,
+) -> Result<Option<Detection>, Error> {
+    if head.len() < core::MAGIC_WIDTH {
+        return Ok(None);
+    }
+    let magic = &head[..core::MAGIC_WIDTH];
+    let mut masked = magic.to_vec();
+    masked[CONTAINER_OFFSET] = core::magic_id()[CONTAINER_OFFSET];
+    if masked != core::magic_id() {
+        return Ok(None);
+    }
+    let payload = bytes - core::MAGIC_WIDTH as u64;
+    let detection = if magic == core::magic_id() {
+        Detection::new(Class::Encrypted, "obg-sealed", Some(ciphertext_details(payload)))
+    } else if magic == core::checked_id() {
+        let details = match payload.checked_sub((16 + TAG_WIDTH) as u64) {
+            Some(length) => format!("{}, key check value and tag", ciphertext_details(length)),
+            None => "truncated key check value or tag".to_string(),
+        };
+        Detection::new(Class::Encrypted, "obg-sealed", Some(details))
+    } else if magic == core::envelope_id() {
+        Detection::new(Class::Encrypted, "obg-envelope", Some(envelope_details(source, head, bytes)?))
+    } else if magic == core::passphrase_id() {
+        let details = match PassphraseHeader::read(&mut Cursor::new(&head[core::MAGIC_WIDTH..])) {
+            Ok((header, _)) => format!("{}, {} cycles", header.scheme, header.cycles),
+            Err(e) => format!("corrupted header: {}", e),
+        };
+        Detection::new(Class::Encrypted, "obg-passphrase", Some(details))
+    } else {
+        Detection::new(
+            Class::Encrypted,
+            "obg-unknown",
+            Some(format!("unknown container version 0x{:02x}", magic[CONTAINER_OFFSET])),
+        )
+    };
+    Ok(Some(detection))
+}
+
+fn ciphertext_details(length: u64) -> String {
+    if length.is_multiple_of(16) {
+        format!("{} bytes of ciphertext", length)
+    } else {
+        format!("{} bytes of truncated ciphertext", length)
+    }
+}
+
+/// counts the recipients and checks the sealed payload and tag which follow them
+fn envelope_details<S: Read + Seek>(source: &mut S, head: &[u8], bytes: u64) -> Result<String, Error> {
+    if head.len() < core::MAGIC_WIDTH + 2 {
+        return Ok("corrupted header".to_string());
+    }
+    let count = u16::from_be_bytes([head[core::MAGIC_WIDTH], head[core::MAGIC_WIDTH + 1]]);
+    let offset = (core::MAGIC_WIDTH + 2 + count as usize * WRAPPED_KEY_WIDTH) as u64;
+    let mut magic = Vec::new();
+    source.seek(SeekFrom::Start(offset))?;
+    source.by_ref().take(core::MAGIC_WIDTH as u64).read_to_end(&mut magic)?;
+    if magic != core::magic_id() {
+        return Ok(format!("{} recipients, corrupted payload", count));
+    }
+    match (bytes - offset - core::MAGIC_WIDTH as u64).checked_sub(TAG_WIDTH as u64) {
+        Some(payload) => Ok(format!(
+            "{} recipients, {} at offset {}, tag",
+            count,
+            ciphertext_details(payload),
+            offset
+        )),
+        None => Ok(format!("{} recipients, truncated tag", count)),
+    }
+}
+
+/// yaml, json or toml documents, exported keys and hex ciphertexts
+fn detect_text(head: &[u8]) -> Detection {
+    if head.is_empty() {
+        return Detection::new(Class::Plain, "empty", None);
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => text.trim(),
+        _ => return Detection::new(Class::Plain, "data", None),
+    };
+    let format = FileFormat::sniff(text);
+    if let Ok(key) = serial::from_str::<Aes256Key>(text, format) {
+        let details = match &key.provenance {
+            Some(provenance) => format!("{}, {}", key.version, provenance.origin),
+            None => key.version,
+        };
+        return Detection::new(Class::Key, &format!("obg-{}-key", format), Some(details));
+    }
+    if let Ok(share) = serial::from_str::<KeyShare>(text, format) {
+        return Detection::new(
+            Class::Key,
+            "obg-key-share",
+            Some(format!("share {} of {}, threshold {}", share.index, share.shares, share.threshold)),
+        );
+    }
+    if serde_yaml::from_str::<X25519Identity>(text).is_ok() {
+        return Detection::new(Class::Key, "x25519-identity", None);
+    }
+    if serde_yaml::from_str::<X25519Recipient>(text).is_ok() {
+        return Detection::new(Class::Key, "x25519-recipient", None);
+    }
+    if let Ok(descriptor) = serial::from_str::<OpaqueDescriptor>(text, format) {
+        return Detection::new(
+            Class::Key,
+            "obg-opaque-descriptor",
+            Some(format!("carved from {}", descriptor.carrier)),
+        );
+    }
+    for format in [KeyFormat::Jwk, KeyFormat::Openssl] {
+        if import_key(text.as_bytes(), &format).is_ok() {
+            return Detection::new(Class::Key, &format!("{}-key", format), None);
+        }
+    }
+    // hex is valid base64 as well, hence tried first, and a hex key is
+    // indistinguishable from 3 blocks of `encrypt text` ciphertext
+    if let Ok(ciphertext) = hex::decode(text) {
+        if !ciphertext.is_empty() && ciphertext.len().is_multiple_of(16) {
+            let details = ciphertext_details(ciphertext.len() as u64);
+            return Detection::new(
+                Class::Encrypted,
+                "hex-ciphertext",
+                Some(match import_key(text.as_bytes(), &KeyFormat::Hex) {
+                    Ok(_) => format!("{}, or a hex key", details),
+                    Err(_) => details,
+                }),
+            );
+        }
+    }
+    if import_key(text.as_bytes(), &KeyFormat::Base64).is_ok() {
+        return Detection::new(Class::Key, "base64-key", None);
+    }
+    Detection::new(Class::Plain, "text", None)
+}
+
+#[cfg(test)]
+mod detect_tests {
+    use crate::aescbc::cdc::Aes256Key;
+    use crate::aescbc::{Aes256CbcCodec, EncryptionEngine};
+    use crate::aescbc::descriptor::OpaqueDescriptor;
+    use crate::aescbc::ie::{export_key, KeyFormat};
+    use crate::detect::{detect, detect_file, exit_code, unreadable, Class};
+    use crate::errors::Error;
+    use crate::pap::{check_value, seal, seal_padded, tag, CHECK_CONTEXT, TAG_WIDTH};
+    use crate::serial::{self, FileFormat};
+    use crate::shamir::split_key;
+    use crate::sneaker::core;
+    use k9::assert_equal;
+    use std::io::Cursor;
+
+    fn key() -> Aes256Key {
+        Aes256Key::new([0x47; 32], [0x54; 16], &[0x35; 237], 37)
+    }
+
+    fn detected(bytes: Vec<u8>) -> Result<(Class, String), Error> {
+        let detection = detect(&mut Cursor::new(bytes))?;
+        Ok((detection.class, detection.format))
+    }
+
+    #[test]
+    pub fn test_detect_containers() -> Result<(), Error> {
+        let mut sealed = core::magic_id();
+        sealed.extend(seal(&key(), b"hogwarts")?);
+        assert_equal!(detected(sealed.clone())?, (Class::Encrypted, "obg-sealed".to_string()));
+
+        let mut checked = core::checked_id();
+        checked.extend(check_value(CHECK_CONTEXT, &key())?);
+        let ciphertext = seal_padded(&key(), b"hogwarts")?;
+        let tag = tag(&key(), &checked, &ciphertext)?;
+        checked.extend(ciphertext);
+        checked.extend(tag);
+        let detection = detect(&mut Cursor::new(checked))?;
+        assert_equal!(detection.format, "obg-sealed");
+        assert_equal!(detection.details, Some("16 bytes of ciphertext, key check value and tag".to_string()));
+
+        let mut unknown = sealed.clone();
+        unknown[7] = 0x37;
+        assert_equal!(detected(unknown)?, (Class::Encrypted, "obg-unknown".to_string()));
+
+        let mut enveloped = core::envelope_id();
+        enveloped.extend([0x00, 0x00]);
+        enveloped.extend(&sealed);
+        let detection = detect(&mut Cursor::new(enveloped.clone()))?;
+        assert_equal!(detection.details, Some("0 recipients, truncated tag".to_string()));
+        enveloped.extend([0x00; TAG_WIDTH]);
+        let detection = detect(&mut Cursor::new(enveloped))?;
+        assert_equal!(detection.format, "obg-envelope");
+        assert_equal!(detection.details, Some("0 recipients, 16 bytes of ciphertext at offset 35, tag".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_detect_keys() -> Result<(), Error> {
+        let key = key();
+        assert_equal!(detected(key.to_bytes("key.bin")?)?, (Class::Key, "obg-binary-key".to_string()));
+        assert_equal!(
+            detected(export_key(&key, &KeyFormat::Yaml, "key.yml")?)?,
+            (Class::Key, "obg-yaml-key".to_string())
+        );
+        let random = export_key(&Aes256Key::random(None)?, &KeyFormat::Yaml, "random.yml")?;
+        let detection = detect(&mut Cursor::new(random))?;
+        assert!(detection.details.unwrap().ends_with(", random"));
+        for format in [KeyFormat::Json, KeyFormat::Toml] {
+            assert_equal!(
+                detected(export_key(&key, &format, "key")?)?,
+                (Class::Key, format!("obg-{}-key", format))
+            );
+        }
+        let descriptor = OpaqueDescriptor {
+            version: "obg-v3.0.1".to_string(),
+            carrier: "/tmp/nothing.png".to_string(),
+            sha3_256: "00".repeat(32),
+            key_offset: Some(18),
+            salt_offset: None,
+            blob_offset: None,
+            middle_out: false,
+        };
+        for format in [FileFormat::Yaml, FileFormat::Toml] {
+            assert_equal!(
+                detected(serial::to_string(&descriptor, format)?.into_bytes())?,
+                (Class::Key, "obg-opaque-descriptor".to_string())
+            );
+        }
+        let share = split_key(&key, 2, 3)?.remove(0);
+        assert_equal!(
+            detected(serial::to_string(&share, FileFormat::Toml)?.into_bytes())?,
+            (Class::Key, "obg-key-share".to_string())
+        );
+        for (format, name) in [
+            (KeyFormat::Jwk, "jwk-key"),
+            (KeyFormat::Openssl, "openssl-key"),
+            (KeyFormat::Base64, "base64-key"),
+        ] {
+            assert_equal!(detected(export_key(&key, &format, "key")?)?, (Class::Key, name.to_string()));
+        }
+        let detection = detect(&mut Cursor::new(export_key(&key, &KeyFormat::Hex, "key")?))?;
+        assert_equal!((detection.class, detection.format), (Class::Encrypted, "hex-ciphertext".to_string()));
+        assert!(detection.details.unwrap().ends_with(", or a hex key"));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_detect_plain() -> Result<(), Error> {
+        assert_equal!(detected(Vec::new())?, (Class::Plain, "empty".to_string()));
+        assert_equal!(detected(vec![0x00, 0x00, 0x02])?, (Class::Plain, "data".to_string()));
+        assert_equal!(detected(b"hogwarts\n".to_vec())?, (Class::Plain, "text".to_string()));
+        assert_equal!(
+            detected(format!("{}\n", "ab".repeat(32)).into_bytes())?,
+            (Class::Encrypted, "hex-ciphertext".to_string())
+        );
+        let codec = Aes256CbcCodec::new_with_key(&key())?;
+        let ciphertext = codec.encrypt_blocks(&[0x35; 40]);
+        assert_equal!(ciphertext.len(), 48);
+        assert_equal!(
+            detected(format!("{}\n", hex::encode(ciphertext)).into_bytes())?,
+            (Class::Encrypted, "hex-ciphertext".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_exit_code() -> Result<(), Error> {
+        let plain = detect(&mut Cursor::new(b"hogwarts".to_vec()))?;
+        let mut sealed = core::magic_id();
+        sealed.extend(seal(&key(), b"hogwarts")?);
+        let sealed = detect(&mut Cursor::new(sealed))?;
+        let binary = detect(&mut Cursor::new(key().to_bytes("key.bin")?))?;
+        assert_equal!(exit_code(std::slice::from_ref(&sealed)), 0);
+        assert_equal!(exit_code(&[sealed.clone(), binary.clone()]), 10);
+        assert_equal!(exit_code(&[plain.clone(), binary, sealed]), 11);
+        let error = detect_file("tests/surely-missing.kgz").unwrap_err();
+        let missing = unreadable("tests/surely-missing.kgz", &error);
+        assert_equal!(missing.class, Class::Unreadable);
+        assert_equal!(missing.path, "tests/surely-missing.kgz");
+        assert_equal!(exit_code(&[missing, plain]), 3);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::errors::Error;
+use crate::errors::{Error, FileSystemError};
 use crate::ioutils::absolute_path;
 use crate::ioutils::open_write;
 use chrono::{DateTime, Utc};
@@ -52,10 +52,11 @@ impl TempEmission {
                 std::fs::create_dir_all(parent)?;
                 Ok(path.to_path_buf())
             }
-            None => Err(Error::FileSystemError(format!(
+            None => Err(FileSystemError::new(format!(
                 "base path does not have an ancestor {}",
                 path.display()
-            ))),
+            ))
+            .into()),
         }
     }
     pub fn papobg_8473776564_file(&self) -> Result<(File, PathBuf), Error> {
@@ -0,0 +1,272 @@
+/*!
+X25519 Envelopes
+
+Wraps a random per-file [`Aes256Key`] to one or more x25519
+recipients such that any of their identities can unwrap it. The
+payload itself still goes through the regular codec stack and the
+file ends with the tag of [`crate::pap::tag`].
+*/
+use crate::aescbc::cdc::Aes256Key;
+use crate::aescbc::secret::SecretBytes;
+use crate::aescbc::tp::{B128, B256, B96};
+use crate::errors::Error;
+use crate::ioutils::{open_write, read_bytes};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256, Sha3_512};
+use std::io::Write;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+pub const WRAP_CONTEXT: &[u8] = b"obg-x25519-v1";
+pub const WRAPPED_KEY_WIDTH: usize = 32 + 48 + 16;
+
+fn getcurrentversion() -> String {
+    format!("obg-v{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn decode_32(label: &str, data: &str) -> Result<[u8; 32], Error> {
+    let bytes = hex::decode(data)?;
+    if bytes.len() != 32 {
+        return Err(Error::EnvelopeError(format!(
+            "{} is {} bytes long instead of 32",
+            label,
+            bytes.len()
+        )));
+    }
+    let mut result = [0u8; 32];
+    result.copy_from_slice(&bytes);
+    Ok(result)
+}
+
+/// public half of an identity to which file keys are wrapped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct X25519Recipient {
+    pub version: String,
+    pub public: String,
+}
+
+impl X25519Recipient {
+    pub fn public_key(&self) -> Result<PublicKey, Error> {
+        Ok(PublicKey::from(decode_32("recipient public key", &self.public)?))
+    }
+    pub fn save_to_yaml_file(&self, filename: String) -> Result<(), Error> {
+        let mut file = open_write(&filename)?;
+        let yaml = serde_yaml::to_string(self)?;
+        Ok(file.write_all(yaml.as_bytes())?)
+    }
+    pub fn load_from_yaml_file(filename: String) -> Result<X25519Recipient, Error> {
+        let bytes = read_bytes(&filename)?;
+        let recipient: X25519Recipient = serde_yaml::from_slice(&bytes)?;
+        Ok(recipient)
+    }
+}
+
+/// secret half of a x25519 keypair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct X25519Identity {
+    pub version: String,
+    #[serde(with = "crate::aescbc::secret::hex")]
+    pub secret: SecretBytes,
+    pub public: String,
+}
+
+impl X25519Identity {
+    pub fn generate() -> X25519Identity {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        X25519Identity {
+            version: getcurrentversion(),
+            secret: SecretBytes::from(&secret.as_bytes()[..]),
+            public: hex::encode(public.as_bytes()),
+        }
+    }
+    pub fn recipient(&self) -> X25519Recipient {
+        X25519Recipient {
+            version: self.version.clone(),
+            public: self.public.clone(),
+        }
+    }
+    pub fn static_secret(&self) -> Result<StaticSecret, Error> {
+        match self.secret.expose().first_chunk::<32>() {
+            Some(secret) if self.secret.len() == 32 => Ok(StaticSecret::from(*secret)),
+            _ => Err(Error::EnvelopeError(format!(
+                "identity secret key is {} bytes long instead of 32",
+                self.secret.len()
+            ))),
+        }
+    }
+    pub fn save_to_yaml_file(&self, filename: String) -> Result<(), Error> {
+        let mut file = open_write(&filename)?;
+        let yaml = serde_yaml::to_string(self)?;
+        Ok(file.write_all(yaml.as_bytes())?)
+    }
+    pub fn load_from_yaml_file(filename: String) -> Result<X25519Identity, Error> {
+        let bytes = read_bytes(&filename)?;
+        let identity: X25519Identity = serde_yaml::from_slice(&bytes)?;
+        Ok(identity)
+    }
+
+    /// attempts to unwrap the file key, returns `None` when the
+    /// entry was wrapped to somebody else
+    pub fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<Option<Aes256Key>, Error> {
+        let secret = self.static_secret()?;
+        let ephemeral = PublicKey::from(wrapped.ephemeral);
+        let shared = secret.diffie_hellman(&ephemeral);
+        if !shared.was_contributory() {
+            return Ok(None);
+        }
+        let kek = KeyEncryptionKey::derive(
+            shared.as_bytes(),
+            &wrapped.ephemeral,
+            PublicKey::from(&secret).as_bytes(),
+        );
+        if !constant_time_eq(&kek.tag(&wrapped.ephemeral, &wrapped.ciphertext), &wrapped.tag) {
+            return Ok(None);
+        }
+        let mut material = Zeroizing::new(wrapped.ciphertext);
+        kek.engine().apply_keystream(&mut *material);
+        let mut skey: B256 = [0; 32];
+        skey.copy_from_slice(&material[..32]);
+        let mut siv: B128 = [0; 16];
+        siv.copy_from_slice(&material[32..]);
+        Ok(Some(Aes256Key::new(skey, siv, &[], 0)))
+    }
+}
+
+struct KeyEncryptionKey {
+    key: B256,
+    nonce: B96,
+    mac: [u8; 20],
+}
+
+impl KeyEncryptionKey {
+    fn derive(shared: &[u8], ephemeral: &[u8], recipient: &[u8]) -> KeyEncryptionKey {
+        let mut hasher = Sha3_512::new();
+        hasher.update(WRAP_CONTEXT);
+        hasher.update(shared);
+        hasher.update(ephemeral);
+        hasher.update(recipient);
+        let digest = hasher.finalize();
+        let mut key: B256 = [0; 32];
+        key.copy_from_slice(&digest[..32]);
+        let mut nonce: B96 = [0; 12];
+        nonce.copy_from_slice(&digest[32..44]);
+        let mut mac = [0u8; 20];
+        mac.copy_from_slice(&digest[44..]);
+        KeyEncryptionKey { key, nonce, mac }
+    }
+    fn engine(&self) -> ChaCha20 {
+        ChaCha20::new(&self.key.into(), &self.nonce.into())
+    }
+    fn tag(&self, ephemeral: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.mac);
+        hasher.update(ephemeral);
+        hasher.update(ciphertext);
+        let mut tag = [0u8; 16];
+        tag.copy_from_slice(&hasher.finalize()[..16]);
+        tag
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// file key wrapped to a single recipient as stored in the envelope header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub ephemeral: [u8; 32],
+    pub ciphertext: [u8; 48],
+    pub tag: [u8; 16],
+}
+
+impl WrappedKey {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(WRAPPED_KEY_WIDTH);
+        bytes.extend(self.ephemeral);
+        bytes.extend(self.ciphertext);
+        bytes.extend(self.tag);
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<WrappedKey, Error> {
+        if bytes.len() != WRAPPED_KEY_WIDTH {
+            return Err(Error::EnvelopeError(format!(
+                "wrapped key is {} bytes long instead of {}",
+                bytes.len(),
+                WRAPPED_KEY_WIDTH
+            )));
+        }
+        let mut ephemeral = [0u8; 32];
+        ephemeral.copy_from_slice(&bytes[..32]);
+        let mut ciphertext = [0u8; 48];
+        ciphertext.copy_from_slice(&bytes[32..80]);
+        let mut tag = [0u8; 16];
+        tag.copy_from_slice(&bytes[80..]);
+        Ok(WrappedKey {
+            ephemeral,
+            ciphertext,
+            tag,
+        })
+    }
+}
+
+/// generates a random key for a single file
+pub fn file_key() -> Aes256Key {
+    let mut skey: B256 = [0; 32];
+    OsRng.fill_bytes(&mut skey);
+    let mut siv: B128 = [0; 16];
+    OsRng.fill_bytes(&mut siv);
+    Aes256Key::new(skey, siv, &[], 0)
+}
+
+/// wraps the key and iv of `key` to `recipient`
+pub fn wrap_key(key: &Aes256Key, recipient: &X25519Recipient) -> Result<WrappedKey, Error> {
+    let public = recipient.public_key()?;
+    let secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&public);
+    if !shared.was_contributory() {
+        return Err(Error::EnvelopeError(format!(
+            "invalid recipient public key {}",
+            recipient.public
+        )));
+    }
+    let kek = KeyEncryptionKey::derive(shared.as_bytes(), ephemeral.as_bytes(), public.as_bytes());
+    let mut ciphertext = [0u8; 48];
+    ciphertext[..32].copy_from_slice(key.skey()?);
+    ciphertext[32..].copy_from_slice(key.siv()?);
+    kek.engine().apply_keystream(&mut ciphertext);
+    Ok(WrappedKey {
+        ephemeral: *ephemeral.as_bytes(),
+        tag: kek.tag(ephemeral.as_bytes(), &ciphertext),
+        ciphertext,
+    })
+}
+
+#[cfg(test)]
+mod envelope_tests {
+    use crate::envelope::{file_key, wrap_key, WrappedKey, X25519Identity};
+    use crate::errors::Error;
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_wrap_unwrap_key() -> Result<(), Error> {
+        let alice = X25519Identity::generate();
+        let bob = X25519Identity::generate();
+        let key = file_key();
+
+        let wrapped = wrap_key(&key, &alice.recipient())?;
+        let wrapped = WrappedKey::from_bytes(&wrapped.to_bytes())?;
+
+        let unwrapped = alice.unwrap_key(&wrapped)?.expect("alice should unwrap the key");
+        assert_equal!(unwrapped.skey()?, key.skey()?);
+        assert_equal!(unwrapped.siv()?, key.siv()?);
+        assert_equal!(bob.unwrap_key(&wrapped)?, None);
+        Ok(())
+    }
+}
@@ -2,18 +2,60 @@ use hex::FromHexError;
 use std::num::ParseIntError;
 use std::string::FromUtf8Error;
 
+/// Represents the stages of encryption/decryption principally during I/O,
+/// failures of the file functions of [`crate::pap`] carry the stage at
+/// which they happened, see [`Error::stage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IOStage {
+    InitCodec,
+    Read,
+    Accept,
+    Metadata,
+    Transcode,
+    Write,
+}
+impl From<IOStage> for String {
+    fn from(stage: IOStage) -> String {
+        String::from(match stage {
+            IOStage::InitCodec => "InitCodec",
+            IOStage::Read => "Read",
+            IOStage::Accept => "Accept",
+            IOStage::Metadata => "Metadata",
+            IOStage::Transcode => "Transcode",
+            IOStage::Write => "Write",
+        })
+    }
+}
+impl std::fmt::Display for IOStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", String::from(*self))
+    }
+}
+impl From<IOStage> for u8 {
+    fn from(stage: IOStage) -> u8 {
+        match stage {
+            IOStage::InitCodec => 0b000001,
+            IOStage::Read => 0b000010,
+            IOStage::Accept => 0b000100,
+            IOStage::Metadata => 0b001000,
+            IOStage::Transcode => 0b010000,
+            IOStage::Write => 0b100000,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     IOError(std::io::Error),
     TemplateError(indicatif::style::TemplateError),
-    FileSystemError(String),
+    FileSystemError(FileSystemError),
     EncryptionError(EncryptionError),
     DecryptionError(DecryptionError),
     HexDecodingError(String),
     DeserializationError(String),
     NumberParsingError(ParseIntError),
     UriParseError(String),
-    SerializationError(String),
+    SerializationError(serde_json::Error),
     KeyError(String),
     NonValidKey(String),
     InvalidAes256KeySize(String),
@@ -22,6 +64,25 @@ pub enum Error {
     InvalidCliArg(String),
     InvalidAesIvSize(String),
     YamlFileError(YamlFileError),
+    SecretSharingError(String),
+    EnvelopeError(String),
+    InvalidSource(String),
+    PromptError(String),
+    PassphraseError(String),
+    AlreadyEncrypted(String),
+    NotEncrypted(String),
+    AlreadyExists(String),
+    EmptyPassword(String),
+    EmptySalt(String),
+    InvalidKeyHex(String),
+    YamlError(serde_yaml::Error),
+    UrlError(url::ParseError),
+    ForeignCallError(String),
+    Staged {
+        stage: IOStage,
+        path: String,
+        source: Box<Error>,
+    },
 }
 
 impl std::fmt::Display for Error {
@@ -45,11 +106,208 @@ impl std::fmt::Display for Error {
             Error::InvalidUtf8(s) => write!(f, "InvalidUtf8: {}", s),
             Error::InvalidConfig(s) => write!(f, "InvalidConfig: {}", s),
             Error::YamlFileError(e) => write!(f, "{}", e),
+            Error::SecretSharingError(s) => write!(f, "SecretSharingError: {}", s),
+            Error::EnvelopeError(s) => write!(f, "EnvelopeError: {}", s),
+            Error::InvalidSource(s) => write!(f, "InvalidSource: {}", s),
+            Error::PromptError(s) => write!(f, "PromptError: {}", s),
+            Error::PassphraseError(s) => write!(f, "PassphraseError: {}", s),
+            Error::AlreadyEncrypted(s) => write!(f, "AlreadyEncrypted: {}", s),
+            Error::NotEncrypted(s) => write!(f, "NotEncrypted: {}", s),
+            Error::AlreadyExists(s) => write!(
+                f,
+                "AlreadyExists: {} already exists, you may pass `-f' to force overwrite",
+                s
+            ),
+            Error::EmptyPassword(s) => write!(f, "EmptyPassword: {}", s),
+            Error::EmptySalt(s) => write!(f, "EmptySalt: {}", s),
+            Error::InvalidKeyHex(s) => write!(f, "InvalidKeyHex: {}", s),
+            Error::YamlError(e) => write!(f, "deserialization error: {}", e),
+            Error::UrlError(e) => write!(f, "failed to parse URI {}", e),
+            Error::ForeignCallError(s) => write!(f, "ForeignCallError: {}", s),
+            Error::Staged { stage, path, .. } => write!(f, "failed at {} stage on {}", stage, path),
         }
     }
 }
 
-impl std::error::Error for Error {}
+/// the message of each variant already includes its payload, so the
+/// source is the cause of the payload rather than the payload itself
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IOError(e) => e.source(),
+            Error::TemplateError(e) => e.source(),
+            Error::FileSystemError(e) => e.source(),
+            Error::EncryptionError(e) => e.source(),
+            Error::DecryptionError(e) => e.source(),
+            Error::SerializationError(e) => e.source(),
+            Error::YamlFileError(e) => e.source(),
+            Error::NumberParsingError(e) => e.source(),
+            Error::InvalidUtf8(e) => e.source(),
+            Error::YamlError(e) => e.source(),
+            Error::UrlError(e) => e.source(),
+            Error::Staged { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// attaches the stage and path at which the error happened,
+    /// errors which already carry a stage are kept as they are
+    pub fn at(self, stage: IOStage, path: &str) -> Error {
+        match self {
+            Error::Staged { .. } => self,
+            error => Error::Staged {
+                stage,
+                path: path.to_string(),
+                source: Box::new(error),
+            },
+        }
+    }
+    /// the stage at which the error happened, if known
+    pub fn stage(&self) -> Option<IOStage> {
+        match self {
+            Error::Staged { stage, .. } => Some(*stage),
+            _ => None,
+        }
+    }
+    /// the message of the error followed by those of its sources, as
+    /// the command-line prints it
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            report.push_str(&format!(": {}", error));
+            source = error.source();
+        }
+        report
+    }
+    /// name of the variant, stable for scripts matching on it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::IOError(_) => "IOError",
+            Error::TemplateError(_) => "TemplateError",
+            Error::FileSystemError(_) => "FileSystemError",
+            Error::EncryptionError(_) => "EncryptionError",
+            Error::DecryptionError(_) => "DecryptionError",
+            Error::HexDecodingError(_) => "HexDecodingError",
+            Error::DeserializationError(_) => "DeserializationError",
+            Error::NumberParsingError(_) => "NumberParsingError",
+            Error::UriParseError(_) => "UriParseError",
+            Error::SerializationError(_) => "SerializationError",
+            Error::KeyError(_) => "KeyError",
+            Error::NonValidKey(_) => "NonValidKey",
+            Error::InvalidAes256KeySize(_) => "InvalidAes256KeySize",
+            Error::InvalidConfig(_) => "InvalidConfig",
+            Error::InvalidUtf8(_) => "InvalidUtf8",
+            Error::InvalidCliArg(_) => "InvalidCliArg",
+            Error::InvalidAesIvSize(_) => "InvalidAesIvSize",
+            Error::YamlFileError(_) => "YamlFileError",
+            Error::SecretSharingError(_) => "SecretSharingError",
+            Error::EnvelopeError(_) => "EnvelopeError",
+            Error::InvalidSource(_) => "InvalidSource",
+            Error::PromptError(_) => "PromptError",
+            Error::PassphraseError(_) => "PassphraseError",
+            Error::AlreadyEncrypted(_) => "AlreadyEncrypted",
+            Error::NotEncrypted(_) => "NotEncrypted",
+            Error::AlreadyExists(_) => "AlreadyExists",
+            Error::EmptyPassword(_) => "EmptyPassword",
+            Error::EmptySalt(_) => "EmptySalt",
+            Error::InvalidKeyHex(_) => "InvalidKeyHex",
+            Error::YamlError(_) => "YamlError",
+            Error::UrlError(_) => "UrlError",
+            Error::ForeignCallError(_) => "ForeignCallError",
+            Error::Staged { .. } => "Staged",
+        }
+    }
+    /// the error without the stage context
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Staged { source, .. } => source.root(),
+            error => error,
+        }
+    }
+}
+
+/// attaches [`IOStage`] context to fallible operations
+pub trait StageContext<T> {
+    fn at(self, stage: IOStage, path: &str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> StageContext<T> for Result<T, E> {
+    fn at(self, stage: IOStage, path: &str) -> Result<T, Error> {
+        self.map_err(|e| e.into().at(stage, path))
+    }
+}
+
+/// Exit codes of the `obg` command-line, stable across releases
+///
+/// | code   | meaning                                                  |
+/// |--------|----------------------------------------------------------|
+/// | `0`    | success                                                  |
+/// | `1`    | unexpected failure                                       |
+/// | `2`    | invalid command-line arguments                           |
+/// | `3`    | input/output or file-system failure                      |
+/// | `4`    | invalid, corrupted or mismatched key                     |
+/// | `5`    | decryption failure, wrong passphrase or identity         |
+/// | `6`    | invalid config                                           |
+/// | `7`    | missing, empty or unreadable password or salt            |
+/// | `10`   | `obg id` found a key and no plain file                   |
+/// | `11`   | `obg id` found a plain file                              |
+/// | `0x54` | input already encrypted or not encrypted                 |
+/// | `0xdc` | output already exists and `--force` was not given        |
+pub mod exit {
+    pub const SUCCESS: u8 = 0;
+    pub const FAILURE: u8 = 1;
+    pub const USAGE: u8 = 2;
+    pub const IO: u8 = 3;
+    pub const KEY: u8 = 4;
+    pub const DECRYPTION: u8 = 5;
+    pub const CONFIG: u8 = 6;
+    pub const SECRET: u8 = 7;
+    pub const ID_KEY: u8 = 10;
+    pub const ID_PLAIN: u8 = 11;
+    pub const ENCRYPTION_STATE: u8 = 0x54;
+    pub const ALREADY_EXISTS: u8 = 0xdc;
+}
+
+impl Error {
+    /// exit code of the `obg` command-line for this error, see [`exit`]
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::InvalidCliArg(_)
+            | Error::NumberParsingError(_)
+            | Error::UriParseError(_)
+            | Error::UrlError(_)
+            | Error::ForeignCallError(_) => exit::USAGE,
+            Error::IOError(_) | Error::FileSystemError(_) | Error::YamlFileError(_) => exit::IO,
+            Error::KeyError(_)
+            | Error::NonValidKey(_)
+            | Error::InvalidAes256KeySize(_)
+            | Error::InvalidAesIvSize(_)
+            | Error::InvalidKeyHex(_)
+            | Error::HexDecodingError(_)
+            | Error::SecretSharingError(_) => exit::KEY,
+            Error::DecryptionError(_) | Error::EnvelopeError(_) | Error::PassphraseError(_) => {
+                exit::DECRYPTION
+            }
+            Error::InvalidConfig(_) => exit::CONFIG,
+            Error::EmptyPassword(_)
+            | Error::EmptySalt(_)
+            | Error::InvalidSource(_)
+            | Error::PromptError(_) => exit::SECRET,
+            Error::AlreadyEncrypted(_) | Error::NotEncrypted(_) => exit::ENCRYPTION_STATE,
+            Error::AlreadyExists(_) => exit::ALREADY_EXISTS,
+            Error::TemplateError(_)
+            | Error::EncryptionError(_)
+            | Error::DeserializationError(_)
+            | Error::SerializationError(_)
+            | Error::YamlError(_)
+            | Error::InvalidUtf8(_) => exit::FAILURE,
+            Error::Staged { source, .. } => source.exit_code(),
+        }
+    }
+}
 
 impl From<ParseIntError> for Error {
     fn from(e: ParseIntError) -> Self {
@@ -58,12 +316,12 @@ impl From<ParseIntError> for Error {
 }
 impl From<serde_yaml::Error> for Error {
     fn from(e: serde_yaml::Error) -> Self {
-        Error::DeserializationError(e.to_string())
+        Error::YamlError(e)
     }
 }
 impl From<url::ParseError> for Error {
     fn from(e: url::ParseError) -> Self {
-        Error::UriParseError(e.to_string())
+        Error::UrlError(e)
     }
 }
 impl From<std::io::Error> for Error {
@@ -96,6 +354,11 @@ impl From<FromHexError> for Error {
         }
     }
 }
+impl From<FileSystemError> for Error {
+    fn from(e: FileSystemError) -> Self {
+        Error::FileSystemError(e)
+    }
+}
 
 impl From<YamlFileError> for Error {
     fn from(e: YamlFileError) -> Self {
@@ -114,50 +377,174 @@ impl From<DecryptionError> for Error {
     }
 }
 
+/// the error underneath a message, see [`std::error::Error::source`]
+pub type Cause = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+fn cause_of(source: &Option<Cause>) -> Option<&(dyn std::error::Error + 'static)> {
+    match source {
+        Some(source) => Some(source.as_ref()),
+        None => None,
+    }
+}
+
 #[derive(Debug)]
 pub struct YamlFileError {
     message: String,
+    source: Option<Cause>,
 }
 impl std::fmt::Display for YamlFileError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.message)
     }
 }
+impl std::error::Error for YamlFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        cause_of(&self.source)
+    }
+}
 
 impl YamlFileError {
     pub fn with_message(message: String) -> YamlFileError {
-        YamlFileError { message }
+        YamlFileError { message, source: None }
+    }
+    pub fn with_source<E: Into<Cause>>(message: String, source: E) -> YamlFileError {
+        YamlFileError {
+            message,
+            source: Some(source.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileSystemError {
+    message: String,
+    source: Option<std::io::Error>,
+}
+impl std::fmt::Display for FileSystemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+impl std::error::Error for FileSystemError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.source {
+            Some(source) => Some(source),
+            None => None,
+        }
+    }
+}
+
+impl FileSystemError {
+    pub fn new(message: String) -> FileSystemError {
+        FileSystemError { message, source: None }
+    }
+    pub fn with_source(message: String, source: std::io::Error) -> FileSystemError {
+        FileSystemError {
+            message,
+            source: Some(source),
+        }
+    }
+    /// the same error with `context` prepended to its message
+    pub fn context(self, context: &str) -> FileSystemError {
+        FileSystemError {
+            message: format!("{}: {}", context, self.message),
+            ..self
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct EncryptionError {
     reason: String,
+    source: Option<Cause>,
 }
 impl std::fmt::Display for EncryptionError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.reason)
     }
 }
+impl std::error::Error for EncryptionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        cause_of(&self.source)
+    }
+}
 
 impl EncryptionError {
     pub fn new(reason: String) -> EncryptionError {
-        EncryptionError { reason }
+        EncryptionError { reason, source: None }
+    }
+    pub fn with_source<E: Into<Cause>>(reason: String, source: E) -> EncryptionError {
+        EncryptionError {
+            reason,
+            source: Some(source.into()),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct DecryptionError {
     reason: String,
+    source: Option<Cause>,
 }
 impl std::fmt::Display for DecryptionError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.reason)
     }
 }
+impl std::error::Error for DecryptionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        cause_of(&self.source)
+    }
+}
 
 impl DecryptionError {
     pub fn new(reason: String) -> DecryptionError {
-        DecryptionError { reason }
+        DecryptionError { reason, source: None }
+    }
+    pub fn with_source<E: Into<Cause>>(reason: String, source: E) -> DecryptionError {
+        DecryptionError {
+            reason,
+            source: Some(source.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod errors_tests {
+    use crate::errors::{Error, FileSystemError, IOStage};
+    use crate::ioutils::open_read;
+    use crate::serial::{self, FileFormat};
+    use k9::assert_equal;
+    use std::error::Error as StdError;
+
+    fn depth(error: &Error) -> usize {
+        let mut depth = 0;
+        let mut source = error.source();
+        while let Some(error) = source {
+            depth += 1;
+            source = error.source();
+        }
+        depth
+    }
+
+    #[test]
+    pub fn test_source_chains() {
+        let error: Error = hex::decode("0").unwrap_err().into();
+        assert!(error.source().is_none());
+        assert_equal!(error.report(), "cannot parse hex string: odd length");
+
+        let error = open_read("tests/surely-missing.kgz").unwrap_err();
+        assert!(matches!(error, Error::FileSystemError(_)));
+        assert!(error.source().unwrap().is::<std::io::Error>());
+        assert_equal!(depth(&error), 1);
+
+        let error = serial::from_str::<crate::config::Config>("{", FileFormat::Json).unwrap_err();
+        assert!(error.source().unwrap().is::<serde_json::Error>());
+
+        let error = Error::from(FileSystemError::new("no cause".to_string())).at(IOStage::Read, "x");
+        assert!(matches!(error.root(), Error::FileSystemError(_)));
+        assert_equal!(depth(&error), 1);
+        assert_equal!(format!("{}", error), "failed at Read stage on x");
+        assert_equal!(error.report(), "failed at Read stage on x: FileSystemError: no cause");
     }
 }
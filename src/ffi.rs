@@ -0,0 +1,213 @@
+/*!
+C ABI
+
+Built with `--features ffi` into a `cdylib` whose interface is declared in
+`ffi/obg.h`:
+
+- keys returned by `obg_key_load` or `obg_key_from_yaml` belong to the
+  caller and are released with `obg_key_free`
+- buffers returned through `out`/`out_len` belong to the caller and are
+  released with `obg_buffer_free`
+- buffers and strings passed in remain owned by the caller and are not
+  retained past the call
+- functions return `0` on success or else the exit code `obg` would exit
+  with, and `obg_last_error` describes the failure
+*/
+use crate::aescbc::Aes256Key;
+use crate::errors::Error;
+use crate::vault::Vault;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// opaque handle to a key
+pub struct ObgKey {
+    vault: Vault,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: &Error) {
+    let message = error.report().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// runs `call` reporting its error and turning panics into a failure
+fn guard<F: FnOnce() -> Result<(), Error>>(call: F) -> c_int {
+    let error = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => return 0,
+        Ok(Err(error)) => error,
+        Err(_) => Error::ForeignCallError("panicked".to_string()),
+    };
+    set_last_error(&error);
+    error.exit_code() as c_int
+}
+
+unsafe fn string_arg(name: &str, value: *const c_char) -> Result<String, Error> {
+    if value.is_null() {
+        return Err(Error::ForeignCallError(format!("{} is NULL", name)));
+    }
+    match CStr::from_ptr(value).to_str() {
+        Ok(value) => Ok(value.to_string()),
+        Err(e) => Err(Error::ForeignCallError(format!("{} is not valid utf-8: {}", name, e))),
+    }
+}
+
+unsafe fn key_arg<'a>(key: *const ObgKey) -> Result<&'a Vault, Error> {
+    match key.as_ref() {
+        Some(key) => Ok(&key.vault),
+        None => Err(Error::ForeignCallError("key is NULL".to_string())),
+    }
+}
+
+unsafe fn bytes_arg<'a>(data: *const u8, len: usize) -> Result<&'a [u8], Error> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(Error::ForeignCallError("buffer is NULL".to_string()));
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+unsafe fn give_bytes(bytes: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) -> Result<(), Error> {
+    if out.is_null() || out_len.is_null() {
+        return Err(Error::ForeignCallError("out or out_len is NULL".to_string()));
+    }
+    let bytes = bytes.into_boxed_slice();
+    *out_len = bytes.len();
+    *out = Box::into_raw(bytes) as *mut u8;
+    Ok(())
+}
+
+unsafe fn give_key(key: Aes256Key, out: *mut *mut ObgKey) -> Result<(), Error> {
+    if out.is_null() {
+        return Err(Error::ForeignCallError("out is NULL".to_string()));
+    }
+    *out = Box::into_raw(Box::new(ObgKey { vault: Vault::new(key) }));
+    Ok(())
+}
+
+/// the message of the last failure on the calling thread, or NULL; valid
+/// until the next failing call on the same thread, must not be freed
+#[no_mangle]
+pub extern "C" fn obg_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// loads a yaml or binary key file into `*out`
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn obg_key_load(path: *const c_char, out: *mut *mut ObgKey) -> c_int {
+    guard(|| {
+        let path = string_arg("path", path)?;
+        give_key(Vault::from_key_file(&path)?.key().clone(), out)
+    })
+}
+
+/// parses the contents of a yaml key file into `*out`
+///
+/// # Safety
+/// `yaml` must be a NUL-terminated string and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn obg_key_from_yaml(yaml: *const c_char, out: *mut *mut ObgKey) -> c_int {
+    guard(|| {
+        let yaml = string_arg("yaml", yaml)?;
+        give_key(serde_yaml::from_str(&yaml)?, out)
+    })
+}
+
+/// releases a key, NULL is ignored
+///
+/// # Safety
+/// `key` must come from `obg_key_load` or `obg_key_from_yaml` and not be
+/// used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn obg_key_free(key: *mut ObgKey) {
+    if !key.is_null() {
+        drop(Box::from_raw(key));
+    }
+}
+
+/// encrypts `len` bytes of `data` into a buffer stored in `*out` and `*out_len`
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, `out` and `out_len` must be
+/// valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn obg_encrypt_buffer(
+    key: *const ObgKey,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let ciphertext = key_arg(key)?.encrypt_bytes(bytes_arg(data, len)?)?;
+        give_bytes(ciphertext, out, out_len)
+    })
+}
+
+/// decrypts `len` bytes of `data` into a buffer stored in `*out` and `*out_len`
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, `out` and `out_len` must be
+/// valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn obg_decrypt_buffer(
+    key: *const ObgKey,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let plaintext = key_arg(key)?.decrypt_bytes(bytes_arg(data, len)?)?;
+        give_bytes(plaintext, out, out_len)
+    })
+}
+
+/// releases a buffer returned by `obg_encrypt_buffer` or
+/// `obg_decrypt_buffer`, NULL is ignored
+///
+/// # Safety
+/// `data` and `len` must be exactly those returned and `data` not be used
+/// afterwards
+#[no_mangle]
+pub unsafe extern "C" fn obg_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// encrypts `input` into `output` as `obg encrypt file` does
+///
+/// # Safety
+/// `input` and `output` must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn obg_encrypt_file(key: *const ObgKey, input: *const c_char, output: *const c_char) -> c_int {
+    guard(|| {
+        key_arg(key)?.encrypt_file(&string_arg("input", input)?, &string_arg("output", output)?)?;
+        Ok(())
+    })
+}
+
+/// decrypts `input` into `output` as `obg decrypt file` does
+///
+/// # Safety
+/// `input` and `output` must be NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn obg_decrypt_file(key: *const ObgKey, input: *const c_char, output: *const c_char) -> c_int {
+    guard(|| {
+        key_arg(key)?.decrypt_file(&string_arg("input", input)?, &string_arg("output", output)?)?;
+        Ok(())
+    })
+}
@@ -0,0 +1,157 @@
+/*!
+Process Hardening
+
+Keeps plaintext and keys out of core dumps, swap and debuggers. On
+Linux [`harden`] marks the process non-dumpable, which also refuses
+ptrace attachment from unprivileged processes, and locks its memory:
+every page when the memlock limit allows it, or else the buffers of
+[`crate::aescbc::SecretBytes`] as they are created.
+
+Other platforms are left as they are and report no protection.
+*/
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static LOCK_SECRETS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryLock {
+    /// `mlockall` of current and future pages
+    All,
+    /// `mlock` of each key buffer
+    Secrets,
+    None,
+}
+
+impl fmt::Display for MemoryLock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryLock::All => write!(f, "all pages"),
+            MemoryLock::Secrets => write!(f, "key buffers"),
+            MemoryLock::None => write!(f, "none"),
+        }
+    }
+}
+
+/// protections in effect for the current process
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Protections {
+    /// false with `--insecure-allow-dumps`
+    pub enabled: bool,
+    pub core_dumps_disabled: bool,
+    pub ptrace_blocked: bool,
+    pub memory_lock: MemoryLock,
+}
+
+impl Protections {
+    /// what `--insecure-allow-dumps` leaves in effect
+    pub fn insecure() -> Protections {
+        Protections {
+            enabled: false,
+            core_dumps_disabled: false,
+            ptrace_blocked: false,
+            memory_lock: MemoryLock::None,
+        }
+    }
+}
+
+impl fmt::Display for Protections {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = |on: bool, yes: &'static str| if on { yes } else { "allowed" };
+        if !self.enabled {
+            writeln!(f, "hardening: disabled by --insecure-allow-dumps")?;
+        }
+        writeln!(f, "core dumps: {}", state(self.core_dumps_disabled, "disabled"))?;
+        writeln!(f, "ptrace attach: {}", state(self.ptrace_blocked, "blocked"))?;
+        write!(f, "memory lock: {}", self.memory_lock)
+    }
+}
+
+/// applies every protection available and reports those in effect
+#[cfg(target_os = "linux")]
+pub fn harden() -> Protections {
+    // SAFETY: these calls only change attributes of the calling process
+    unsafe {
+        let no_core = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        let core_limited = libc::setrlimit(libc::RLIMIT_CORE, &no_core) == 0;
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+        let dumpable = libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) != 0;
+
+        let mut memlock = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        let unlimited = libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut memlock) == 0
+            && memlock.rlim_cur == libc::RLIM_INFINITY;
+        // with a bounded limit MCL_FUTURE would fail allocations of large inputs
+        let memory_lock = if (unlimited || libc::geteuid() == 0)
+            && libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) == 0
+        {
+            MemoryLock::All
+        } else if memlock.rlim_cur > 0 {
+            LOCK_SECRETS.store(true, Ordering::Relaxed);
+            MemoryLock::Secrets
+        } else {
+            MemoryLock::None
+        };
+        Protections {
+            enabled: true,
+            core_dumps_disabled: core_limited || !dumpable,
+            ptrace_blocked: !dumpable,
+            memory_lock,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn harden() -> Protections {
+    Protections {
+        enabled: true,
+        ..Protections::insecure()
+    }
+}
+
+/// keeps the pages of `bytes` out of swap when [`harden`] settled for
+/// [`MemoryLock::Secrets`], best effort
+///
+/// Pages stay locked once the buffer is dropped since they may hold
+/// other secrets which `munlock` would expose again.
+pub fn lock(bytes: &[u8]) {
+    #[cfg(target_os = "linux")]
+    if !bytes.is_empty() && LOCK_SECRETS.load(Ordering::Relaxed) {
+        // SAFETY: the range is a live allocation borrowed for the call
+        unsafe {
+            libc::mlock(bytes.as_ptr() as *const libc::c_void, bytes.len());
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = bytes;
+}
+
+#[cfg(test)]
+mod harden_tests {
+    use crate::harden::{MemoryLock, Protections};
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_protections_report() {
+        let protections = Protections {
+            enabled: true,
+            core_dumps_disabled: true,
+            ptrace_blocked: true,
+            memory_lock: MemoryLock::Secrets,
+        };
+        assert_equal!(
+            format!("{}", protections),
+            "core dumps: disabled\nptrace attach: blocked\nmemory lock: key buffers"
+        );
+        let json = serde_json::to_value(Protections::insecure()).unwrap();
+        assert_equal!(json["enabled"], false);
+        assert_equal!(json["memory_lock"], "none");
+    }
+}
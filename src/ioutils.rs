@@ -1,10 +1,10 @@
-use crate::errors::Error;
+use crate::errors::{Error, FileSystemError};
 use shellexpand;
 
 use std::fs::{File, OpenOptions};
 use std::io::Read;
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct ReadFile {
     pub bytes: Vec<u8>,
@@ -22,12 +22,33 @@ pub fn absolutely_current_path() -> Result<String, Error> {
     let path = std::env::current_dir()?;
     match path.to_str() {
         Some(path) => Ok(absolute_path(path)),
-        None => Err(Error::FileSystemError(format!("invalid current path"))),
+        None => Err(FileSystemError::new("invalid current path".to_string()).into()),
     }
 }
 
+/// `src` expanded, made absolute against the current directory and, when
+/// it exists, canonicalized
+pub fn canonical_path(src: &str) -> String {
+    let path = PathBuf::from(absolute_path(src));
+    let path = match (path.is_absolute(), std::env::current_dir()) {
+        (false, Ok(current)) => current.join(path),
+        _ => path,
+    };
+    match path.canonicalize() {
+        Ok(canonical) => format!("{}", canonical.display()),
+        Err(_) => format!("{}", path.display()),
+    }
+}
+
+/// the [`canonical_path`] of `src` with the home directory shortened to `~`
 pub fn resolved_path(src: &str) -> String {
-    absolute_path(src).replace(&homedir(), "~")
+    let path = canonical_path(src);
+    let home = homedir();
+    match Path::new(&path).strip_prefix(&home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path,
+    }
 }
 
 pub fn homedir() -> String {
@@ -47,10 +68,11 @@ pub fn get_or_create_ancestor_dir(path: &str) -> Result<String, Error> {
             std::fs::create_dir_all(parent)?;
             Ok(format!("{}", parent.display()))
         }
-        None => Err(Error::FileSystemError(format!(
+        None => Err(FileSystemError::new(format!(
             "base path does not have an ancestor {}",
             path.display()
-        ))),
+        ))
+        .into()),
     }
 }
 
@@ -69,25 +91,46 @@ pub fn read_bytes(filename: &str) -> Result<Vec<u8>, Error> {
     Ok(bytes)
 }
 
+/// opens `target` for writing, creating it with mode 0600 or truncating
+/// it such that no trailing bytes of a longer previous content remain
 pub fn open_write(target: &str) -> Result<std::fs::File, Error> {
     let location = absolute_path(target);
     get_or_create_ancestor_dir(&location)?;
     Ok(OpenOptions::new()
         .create(!Path::new(&location).try_exists()?)
         .write(true)
+        .truncate(true)
         .mode(0o600)
         .open(target)?)
 }
 
 pub fn open_read(target: &str) -> Result<std::fs::File, Error> {
     let location = absolute_path(target);
-    let path = Path::new(&location);
-    if path.exists() {
-        Ok(OpenOptions::new().read(true).open(path)?)
-    } else {
-        Err(Error::FileSystemError(format!(
-            "{} does not exist",
-            location
-        )))
+    match OpenOptions::new().read(true).open(&location) {
+        Ok(file) => Ok(file),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            Err(FileSystemError::with_source(format!("{} does not exist", location), error).into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod ioutils_tests {
+    use crate::emit::TempEmission;
+    use crate::errors::Error;
+    use crate::ioutils::{open_write, read_bytes};
+    use k9::assert_equal;
+    use std::io::Write;
+
+    #[test]
+    pub fn test_open_write_truncates() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        file.write_all(b"a longer previous content")?;
+        let path = format!("{}", path.display());
+        open_write(&path)?.write_all(b"shorter")?;
+        assert_equal!(read_bytes(&path)?, b"shorter".to_vec());
+        Ok(())
     }
 }
@@ -1,11 +1,26 @@
 pub mod aescbc;
+pub mod bench;
 pub mod clap;
 pub mod config;
+pub mod detect;
 pub mod emit;
+pub mod envelope;
 pub mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod harden;
 pub mod hashis;
 pub mod ioutils;
+pub mod output;
 pub mod pap;
+pub mod passphrase;
+pub mod progress;
+pub mod prompt;
 pub mod ccs;
 pub mod serial;
+pub mod shamir;
 pub mod sneaker;
+pub mod sources;
+pub mod vault;
+
+pub use vault::{KeyBuilder, Vault};
@@ -1,20 +1,99 @@
-use clap_builder::Parser;
 use console;
 use hex;
 
-use obg::aescbc::Aes256CbcCodec;
+use obg::aescbc::check_ciphertext;
+use obg::aescbc::{Aes256CbcCodec, Aes256Key};
+use obg::bench;
 use obg::aescbc::EncryptionEngine;
-use obg::clap::{Cli, Command, Decrypt, Encrypt};
-use obg::clap::{KeyDeriver, KeyLoader};
-use obg::errors::Error;
+use obg::aescbc::{export_key, import_key, OpaqueDescriptor};
+use obg::clap::{Cli, Command, ConfigCommand, Decrypt, Encrypt, KeyCommand};
+use obg::config::{config_path, Config, GeoConfig};
+use obg::clap::{KeyDeriver, KeyLoader, KeyOptions};
+use obg::envelope::{X25519Identity, X25519Recipient};
+use obg::detect::{self, detect_file};
+use obg::errors::{exit, DecryptionError, Error};
+use obg::harden::{harden, Protections};
+use obg::output::{Output, OutputFormat};
+use obg::serial::{ConfigFile, FileFormat};
 use obg::ioutils::absolute_path;
 use obg::ioutils::file_exists;
-use obg::pap::{decrypt_file, encrypt_file};
-use obg::sneaker::io::{is_snuck, xstack};
-use std::fs::File;
+use obg::ioutils::open_write;
+use obg::pap::{
+    decrypt_file, decrypt_file_with_identity, decrypt_file_with_passphrase, decrypt_to_memory,
+    decrypt_to_memory_with_identity, decrypt_to_memory_with_passphrase, encrypt_file,
+    encrypt_file_for_recipients, encrypt_file_with_passphrase, Decrypted,
+};
+use obg::progress::Silent;
+use obg::shamir::{combine_key, split_key, KeyShare};
+use std::io::Write;
+use std::path::Path;
 // use url::{Url, Host, Position};
 
-fn main() -> Result<(), Error> {
+/// fails with [`Error::AlreadyExists`] unless `path` is absent or `force` is given
+fn refuse_overwrite(path: &str, force: bool) -> Result<(), Error> {
+    if file_exists(path) && !force {
+        return Err(Error::AlreadyExists(path.to_string()));
+    }
+    Ok(())
+}
+
+/// how `obg verify` decrypts each file
+enum Decryptor {
+    Identity(X25519Identity),
+    Passphrase(Vec<u8>),
+    Key(KeyOptions),
+}
+
+impl Decryptor {
+    /// decrypts `path` in memory, failing unless its tag was checked
+    fn verify(&self, config: Option<&Config>, path: &str) -> Result<Decrypted, Error> {
+        let decrypted = match self {
+            Decryptor::Identity(identity) => decrypt_to_memory_with_identity(identity, path, &Silent)?,
+            Decryptor::Passphrase(passphrase) => decrypt_to_memory_with_passphrase(passphrase, path, &Silent)?,
+            Decryptor::Key(key_opts) => {
+                let mut key_opts = key_opts.clone();
+                key_opts.select(config, path);
+                decrypt_to_memory(&key_opts.load_key()?, path, &Silent)?
+            }
+        };
+        if !decrypted.authenticated {
+            return Err(DecryptionError::new(format!(
+                "{} carries no tag to authenticate it, decrypt and encrypt it again to add one",
+                path
+            ))
+            .into());
+        }
+        Ok(decrypted)
+    }
+}
+
+/// exits with the code documented in [`obg::errors::exit`]
+fn main() {
+    let mate = Cli::parse_with_versions();
+    let protections = match mate.insecure_allow_dumps {
+        true => Protections::insecure(),
+        false => harden(),
+    };
+    let name = match &mate.command {
+        Some(command) => command.name(),
+        None => "version",
+    };
+    let format = match &mate.command {
+        Some(Command::Bench(args)) if args.json => {
+            eprintln!("{} bench --json is deprecated, use --output json", console::style("Warning:").color256(214));
+            OutputFormat::Json
+        }
+        _ => mate.output,
+    };
+    let mut output = Output::new(format, name);
+    let result = run(mate, &protections, &mut output);
+    let code = output.finish(result);
+    if code != exit::SUCCESS {
+        std::process::exit(code as i32);
+    }
+}
+
+fn run(mate: Cli, protections: &Protections, output: &mut Output) -> Result<u8, Error> {
     std::panic::set_hook(Box::new(|panic_info| {
         if let Some(y) = panic_info.payload().downcast_ref::<&str>() {
             eprintln!(
@@ -27,67 +106,332 @@ fn main() -> Result<(), Error> {
         }
     }));
 
-    let mate = Cli::parse();
-    match mate.command {
+    let command = match mate.command {
+        Some(command) => command,
+        None => {
+            output.text(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+            if mate.verbose {
+                output.data(protections)?;
+                if !output.is_json() {
+                    println!("{}", protections);
+                }
+            }
+            return Ok(exit::SUCCESS);
+        }
+    };
+    // only commands which use the config may fail on a malformed one,
+    // such that `config init --force` can still replace it
+    let config_file = config_path();
+    let config = match &command {
+        Command::Id(_) | Command::Key(_) | Command::Bench(_) | Command::Config(ConfigCommand::Init(_)) => None,
+        _ => Config::load(&config_file)?,
+    };
+    let geo = match &config {
+        Some(config) => config.get_default().clone(),
+        None => GeoConfig::default()?,
+    };
+    match command {
         Command::Id(args) => {
+            let mut detections = Vec::new();
             for reference in args.filenames.iter() {
                 let path = absolute_path(reference);
-                let mut file = File::open(&path)?;
-                if xstack(&mut file)? {
-                    eprintln!("\x1b[1;38;5;148mbgn\t\x1b[1;38;5;118mY\x1b[0m\t{}", &path);
-                } else {
-                    eprintln!("\x1b[1;38;5;148mbgn\t\x1b[1;38;5;160mN\x1b[0m\t{}", &path);
-                }
-                if is_snuck(&mut file)? {
-                    eprintln!("\x1b[1;38;5;184mmgx\t\x1b[1;38;5;118mY\x1b[0m\t{}", &path);
-                } else {
-                    eprintln!("\x1b[1;38;5;184mmgx\t\x1b[1;38;5;160mN\x1b[0m\t{}", &path);
+                detections.push(match detect_file(&path) {
+                    Ok(detection) => detection,
+                    Err(error) => detect::unreadable(&path, &error),
+                });
+            }
+            let code = detect::exit_code(&detections);
+            for detection in detections {
+                output.detection(detection);
+            }
+            return Ok(code);
+        }
+        Command::Verify(args) => {
+            let decryptor = if let Some(path) = &args.identity {
+                Decryptor::Identity(X25519Identity::load_from_yaml_file(absolute_path(path))?)
+            } else if let Some(passphrase) = args.passphrase()? {
+                Decryptor::Passphrase(passphrase)
+            } else {
+                Decryptor::Key(args.key_opts.clone())
+            };
+            let mut code = exit::SUCCESS;
+            for path in args.files.iter() {
+                let result = decryptor.verify(config.as_ref(), &absolute_path(path));
+                if let (Err(error), exit::SUCCESS) = (&result, code) {
+                    code = error.exit_code();
                 }
+                output.verification(path, &result);
             }
+            return Ok(code);
         }
-        Command::Keygen(args) => {
-            let key_file = absolute_path(&args.output_file);
+        Command::Keygen(mut args) => {
+            if config.is_some() {
+                args.configure(&geo);
+            }
+            if let Some(path) = args.verify.clone() {
+                args.ask_secrets()?;
+                let key_file = absolute_path(&path);
+                let key = Aes256Key::load(key_file.clone())?;
+                args.verify_key(&key)?;
+                output.verified(&key_file, &key.fingerprint()?);
+                return Ok(exit::SUCCESS);
+            }
+            let key_file = absolute_path(args.output_file());
+            if args.x25519 {
+                let recipient_file = format!("{}.pub", key_file);
+                for path in [&key_file, &recipient_file] {
+                    refuse_overwrite(path, args.force)?;
+                }
+                let identity = X25519Identity::generate();
+                identity.save_to_yaml_file(key_file.clone())?;
+                identity.recipient().save_to_yaml_file(recipient_file.clone())?;
+                output.saved(&key_file);
+                output.saved(&recipient_file);
+                return Ok(exit::SUCCESS);
+            }
+            refuse_overwrite(&key_file, args.force)?;
+            args.ask_secrets()?;
+            args.calibrate()?;
             let key = args.derive_key(args.shuffle_iv)?;
-            if file_exists(&key_file) && !args.force {
-                eprintln!(
-                    "{} already exists, you may pass `-f' to force overwrite",
-                    key_file
-                );
-                std::process::exit(0xdc);
-            }
-            if args.yaml {
-                key.save_to_yaml_file(key_file.clone())?;
-            } else {
-                key.save_to_file(key_file.clone())?;
+            match args.text_format() {
+                Some(format) => key.save_to_config_file(key_file.clone(), format)?,
+                None => key.save_to_file(key_file.clone())?,
             };
-            eprintln!("saved {}", key_file);
+            output.saved(&key_file);
+            output.fingerprint(&key.fingerprint()?, false);
         }
         Command::Encrypt(instruction) => match instruction {
-            Encrypt::Text(args) => {
+            Encrypt::Text(mut args) => {
+                args.key_opts.configure(&geo);
                 let key = args.load_key()?;
-                let codec = Aes256CbcCodec::new(key.skey(), key.siv());
+                let codec = Aes256CbcCodec::new_with_key(&key)?;
                 let plaintext = args.load_plaintext()?;
                 let ciphertext = codec.encrypt_blocks(&plaintext);
-                println!("{}", hex::encode(ciphertext));
+                output.text(hex::encode(ciphertext));
             }
-            Encrypt::File(args) => {
-                let key = args.load_key()?;
-                encrypt_file(key, args.input_file, args.output_file)?
+            Encrypt::File(mut args) => {
+                let progress = args.progress()?;
+                let (input_file, output_file) = (args.input_file.clone(), args.output_file.clone());
+                let transcoded = if !args.recipients.is_empty() {
+                    let mut recipients = Vec::<X25519Recipient>::new();
+                    for path in args.recipients.iter() {
+                        recipients.push(X25519Recipient::load_from_yaml_file(absolute_path(path))?);
+                    }
+                    encrypt_file_for_recipients(
+                        &recipients,
+                        args.input_file,
+                        args.output_file,
+                        progress.as_ref(),
+                    )?
+                } else if let Some(passphrase) = args.passphrase()? {
+                    let geo = config.as_ref().map(|_| &geo);
+                    encrypt_file_with_passphrase(
+                        &passphrase,
+                        args.salt_derivation_scheme(geo),
+                        args.cycles(geo),
+                        args.input_file,
+                        args.output_file,
+                        progress.as_ref(),
+                    )?
+                } else {
+                    let explanation = args.key_opts.select(config.as_ref(), &args.input_file);
+                    output.explain(explanation, args.explain);
+                    let key = args.load_key()?;
+                    encrypt_file(key, args.input_file, args.output_file, progress.as_ref())?
+                };
+                output.transcoded(&input_file, &output_file, transcoded.read, transcoded.written);
             }
         },
         Command::Decrypt(instruction) => match instruction {
-            Decrypt::Text(args) => {
+            Decrypt::Text(mut args) => {
+                args.key_opts.configure(&geo);
                 let key = args.load_key()?;
-                let codec = Aes256CbcCodec::new(key.skey(), key.siv());
+                let codec = Aes256CbcCodec::new_with_key(&key)?;
                 let ciphertext = args.load_ciphertext()?;
-                let plaintext = codec.decrypt_blocks(&hex::decode(&ciphertext)?);
-                println!("{}", String::from_utf8(plaintext)?);
+                let ciphertext = hex::decode(&ciphertext)?;
+                check_ciphertext(&ciphertext)?;
+                let plaintext = codec.decrypt_blocks(&ciphertext);
+                output.text(String::from_utf8(plaintext)?);
+            }
+            Decrypt::File(mut args) => {
+                let progress = args.progress()?;
+                let (input_file, output_file) = (args.input_file.clone(), args.output_file.clone());
+                let transcoded = if let Some(path) = args.identity.clone() {
+                    let identity = X25519Identity::load_from_yaml_file(absolute_path(&path))?;
+                    decrypt_file_with_identity(
+                        &identity,
+                        args.input_file,
+                        args.output_file,
+                        progress.as_ref(),
+                    )?
+                } else if let Some(passphrase) = args.passphrase()? {
+                    decrypt_file_with_passphrase(
+                        &passphrase,
+                        args.input_file,
+                        args.output_file,
+                        progress.as_ref(),
+                    )?
+                } else {
+                    let explanation = args.key_opts.select(config.as_ref(), &args.input_file);
+                    output.explain(explanation, args.explain);
+                    let key = args.load_key()?;
+                    decrypt_file(key, args.input_file, args.output_file, progress.as_ref())?
+                };
+                output.transcoded(&input_file, &output_file, transcoded.read, transcoded.written);
+            }
+        },
+        Command::Key(instruction) => match instruction {
+            KeyCommand::Split(args) => {
+                let key = args.load_key()?;
+                let shares = split_key(&key, args.threshold, args.shares)?;
+                let paths: Vec<String> = shares
+                    .iter()
+                    .map(|share| absolute_path(&args.share_file(share.index)))
+                    .collect();
+                for path in paths.iter() {
+                    refuse_overwrite(path, args.force)?;
+                }
+                for (share, path) in shares.iter().zip(paths.iter()) {
+                    share.save_to_config_file(path.clone(), args.format)?;
+                    output.saved(path);
+                }
+                output.fingerprint(&key.fingerprint()?, true);
             }
-            Decrypt::File(args) => {
+            KeyCommand::Combine(args) => {
+                let key_file = absolute_path(&args.output_file);
+                refuse_overwrite(&key_file, args.force)?;
+                let mut shares = Vec::<KeyShare>::new();
+                for path in args.shares.iter() {
+                    shares.push(KeyShare::load_from_config_file(absolute_path(path))?);
+                }
+                let key = combine_key(&shares)?;
+                match args.text_format() {
+                    Some(format) => key.save_to_config_file(key_file.clone(), format)?,
+                    None => key.save_to_file(key_file.clone())?,
+                };
+                output.saved_with_fingerprint(&key_file, &key.fingerprint()?);
+            }
+            KeyCommand::Export(args) => {
                 let key = args.load_key()?;
-                decrypt_file(key, args.input_file, args.output_file)?
+                if !args.format.carries_blob() {
+                    output.warning(format!("{} keys carry no blob, cycles or version", args.format));
+                }
+                match &args.output_file {
+                    Some(path) => {
+                        let path = absolute_path(path);
+                        refuse_overwrite(&path, args.force)?;
+                        let bytes = export_key(&key, &args.format, &path)?;
+                        open_write(&path)?.write_all(&bytes)?;
+                        output.saved(&path);
+                    }
+                    None => {
+                        let name = key.name().unwrap_or(args.key_file.clone());
+                        output.bytes(export_key(&key, &args.format, &name)?)?;
+                    }
+                }
+            }
+            KeyCommand::Import(args) => {
+                let key_file = absolute_path(&args.output_file);
+                refuse_overwrite(&key_file, args.force)?;
+                let key = import_key(&args.load_material()?, &args.format)?;
+                if !args.format.carries_blob() {
+                    output.warning(format!(
+                        "{} keys carry no blob, {} is saved with an empty blob and 0 cycles",
+                        args.format, key_file
+                    ));
+                }
+                let key = match (key.name(), Path::new(&key_file).file_name()) {
+                    (None, Some(name)) => key.with_name(name.to_string_lossy().to_string())?,
+                    _ => key,
+                };
+                match args.text_format() {
+                    Some(format) => key.save_to_config_file(key_file.clone(), format)?,
+                    None => key.save_to_file(key_file.clone())?,
+                };
+                output.saved_with_fingerprint(&key_file, &key.fingerprint()?);
+            }
+            KeyCommand::Describe(args) => {
+                let descriptor_file = absolute_path(&args.output_file);
+                refuse_overwrite(&descriptor_file, args.force)?;
+                let descriptor = OpaqueDescriptor::describe(
+                    &args.carrier,
+                    args.key_offset,
+                    args.salt_offset,
+                    args.blob_offset,
+                    args.mo_offset,
+                )?;
+                let key = descriptor.load_key()?;
+                descriptor.save_to_config_file(descriptor_file.clone(), args.text_format())?;
+                output.saved_with_fingerprint(&descriptor_file, &key.fingerprint()?);
             }
         },
+        Command::Config(instruction) => {
+            let mut config = match config {
+                Some(config) => config,
+                None => match instruction {
+                    ConfigCommand::Init(_) => Config::default()?,
+                    _ => {
+                        return Err(Error::InvalidConfig(format!(
+                            "{} does not exist, you may run `obg config init'",
+                            config_file
+                        )))
+                    }
+                },
+            };
+            match instruction {
+                ConfigCommand::Init(args) => {
+                    refuse_overwrite(&config_file, args.force)?;
+                    let config = Config::default()?;
+                    output.saved(&config.export(&config_file)?);
+                }
+                ConfigCommand::Show(args) => {
+                    if !output.is_json() {
+                        let format = args.format.unwrap_or_else(|| FileFormat::for_path(&config_file));
+                        print!("{}", config.to_string_as(format)?);
+                    }
+                    output.data(&config)?;
+                }
+                ConfigCommand::Set(args) => {
+                    let geo = match &args.path {
+                        None => config.get_default_mut(),
+                        Some(path) => match config.get_path_mut(path) {
+                            Some(geo) => geo,
+                            None => {
+                                return Err(Error::InvalidConfig(format!(
+                                    "{} was not added, you may run `obg config add-path'",
+                                    path
+                                )))
+                            }
+                        },
+                    };
+                    if geo.apply(&args.setting, &args.value)? {
+                        output.saved(&config.export(&config_file)?);
+                    }
+                }
+                ConfigCommand::AddPath(args) => {
+                    let mut geo = config.get_default().clone();
+                    if args.key_file.is_some() {
+                        geo.set_key_file(args.key_file.clone());
+                    }
+                    config.add_path(&args.path, geo);
+                    output.saved(&config.export(&config_file)?);
+                }
+                ConfigCommand::RemovePath(args) => {
+                    config.remove_path(&args.path);
+                    output.saved(&config.export(&config_file)?);
+                }
+            }
+        }
+        Command::Bench(args) => {
+            let measurements = bench::run(&args.sizes(), args.duration)?;
+            output.data(&measurements)?;
+            if !output.is_json() {
+                println!("{:<6} {:<22} {:>9} {:>14}", "group", "name", "size", "rate");
+                for measurement in measurements.iter() {
+                    println!("{}", measurement);
+                }
+            }
+        }
     };
-    Ok(())
+    Ok(exit::SUCCESS)
 }
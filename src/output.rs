@@ -0,0 +1,337 @@
+/*!
+Command Output
+
+Every subcommand reports through an [`Output`] which either prints the
+usual human-readable lines to stderr as they happen or, with
+`--output json`, collects them into a single [`Report`] written to
+stdout once the command finishes, successfully or not.
+*/
+use crate::detect::{Class, Detection};
+use crate::errors::Error;
+use crate::pap::Decrypted;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// human-readable lines on stderr
+    Text,
+    /// a single JSON object on stdout
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FileEntry {
+    pub path: String,
+    /// `read`, `wrote` or `saved`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Verification {
+    pub path: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plaintext_bytes: Option<u64>,
+    /// whether the tag proved the header and ciphertext untouched
+    pub authenticated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ErrorEntry {
+    pub kind: String,
+    pub message: String,
+    pub exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+}
+
+impl From<&Error> for ErrorEntry {
+    fn from(error: &Error) -> ErrorEntry {
+        ErrorEntry {
+            kind: error.root().kind().to_string(),
+            message: error.report(),
+            exit_code: error.exit_code(),
+            stage: error.stage().map(String::from),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub command: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub detections: Vec<Detection>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verifications: Vec<Verification>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// textual result such as a ciphertext, plaintext or exported key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// hex of a binary result which is not valid utf-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+    /// structured result such as the config or benchmark measurements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorEntry>,
+}
+
+pub struct Output {
+    format: OutputFormat,
+    report: Report,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, command: &str) -> Output {
+        Output {
+            format,
+            report: Report {
+                command: command.to_string(),
+                ..Report::default()
+            },
+        }
+    }
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    fn file(&mut self, path: &str, action: &str, bytes: Option<u64>) {
+        self.report.files.push(FileEntry {
+            path: path.to_string(),
+            action: action.to_string(),
+            bytes,
+        });
+    }
+    pub fn saved(&mut self, path: &str) {
+        if !self.is_json() {
+            eprintln!("saved {}", path);
+        }
+        self.file(path, "saved", None);
+    }
+    pub fn saved_with_fingerprint(&mut self, path: &str, fingerprint: &str) {
+        if !self.is_json() {
+            eprintln!("saved {} with fingerprint {}", path, fingerprint);
+        }
+        self.file(path, "saved", None);
+        self.report.fingerprint = Some(fingerprint.to_string());
+    }
+    /// a key which `keygen --verify` re-derived
+    pub fn verified(&mut self, path: &str, fingerprint: &str) {
+        if !self.is_json() {
+            eprintln!("verified {} with fingerprint {}", path, fingerprint);
+        }
+        self.file(path, "verified", None);
+        self.report.fingerprint = Some(fingerprint.to_string());
+    }
+    /// records the fingerprint of the key, printed only if `show`
+    pub fn fingerprint(&mut self, fingerprint: &str, show: bool) {
+        if show && !self.is_json() {
+            eprintln!("fingerprint {}", fingerprint);
+        }
+        self.report.fingerprint = Some(fingerprint.to_string());
+    }
+    pub fn transcoded(&mut self, input: &str, output: &str, read: u64, written: u64) {
+        if !self.is_json() {
+            eprintln!("wrote {}", output);
+        }
+        self.file(input, "read", Some(read));
+        self.file(output, "wrote", Some(written));
+    }
+    /// records why a key was selected, printed only if `show`
+    pub fn explain(&mut self, explanation: String, show: bool) {
+        if show {
+            if !self.is_json() {
+                eprintln!("{}", explanation);
+            }
+            self.report.explanation = Some(explanation);
+        }
+    }
+    /// a caveat about an otherwise successful command
+    pub fn warning(&mut self, warning: String) {
+        if !self.is_json() {
+            eprintln!("{} {}", console::style("Warning:").color256(214), warning);
+        }
+        self.report.warnings.push(warning);
+    }
+    pub fn detection(&mut self, detection: Detection) {
+        if !self.is_json() {
+            let color = match detection.class {
+                Class::Encrypted => 118,
+                Class::Key => 184,
+                Class::Plain | Class::Unreadable => 160,
+            };
+            let details = match &detection.details {
+                Some(details) => format!(" ({})", details),
+                None => String::new(),
+            };
+            eprintln!(
+                "\x1b[1;38;5;{}m{}\t\x1b[1;38;5;148m{}\x1b[0m\t{}{}",
+                color, detection.class, detection.format, detection.path, details
+            );
+        }
+        self.report.detections.push(detection);
+    }
+    pub fn verification(&mut self, path: &str, result: &Result<Decrypted, Error>) {
+        let verification = match result {
+            Ok(decrypted) => Verification {
+                path: path.to_string(),
+                ok: true,
+                bytes: Some(decrypted.read),
+                plaintext_bytes: Some(decrypted.plaintext.len() as u64),
+                authenticated: decrypted.authenticated,
+                error: None,
+            },
+            Err(error) => Verification {
+                path: path.to_string(),
+                ok: false,
+                bytes: None,
+                plaintext_bytes: None,
+                authenticated: false,
+                error: Some(ErrorEntry::from(error)),
+            },
+        };
+        if !self.is_json() {
+            match result {
+                Ok(decrypted) => eprintln!(
+                    "\x1b[1;38;5;118mok\x1b[0m\t{} ({} bytes, {})",
+                    path,
+                    decrypted.read,
+                    match decrypted.authenticated {
+                        true => "authenticated",
+                        false => "unauthenticated",
+                    }
+                ),
+                Err(error) => eprintln!("\x1b[1;38;5;160mfailed\x1b[0m\t{}: {}", path, error.report()),
+            }
+        }
+        self.report.verifications.push(verification);
+    }
+    /// a textual result, printed to stdout
+    pub fn text(&mut self, text: String) {
+        if !self.is_json() {
+            println!("{}", text);
+        }
+        self.report.text = Some(text);
+    }
+    /// a result which might not be valid utf-8, written as is to stdout
+    pub fn bytes(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        if !self.is_json() {
+            std::io::stdout().write_all(&bytes)?;
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => self.report.text = Some(text),
+            Err(e) => self.report.hex = Some(hex::encode(e.into_bytes())),
+        }
+        Ok(())
+    }
+    /// a structured result, callers print their own text rendering
+    pub fn data<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        match serde_json::to_value(data) {
+            Ok(value) => self.report.data = Some(value),
+            Err(e) => return Err(Error::SerializationError(e)),
+        }
+        Ok(())
+    }
+
+    /// records the outcome of the command in the report and returns its
+    /// exit code, see [`Output::finish`]
+    pub fn complete(&mut self, result: &Result<u8, Error>) -> u8 {
+        self.report.ok = result.is_ok();
+        self.report.error = result.as_ref().err().map(ErrorEntry::from);
+        match result {
+            Ok(code) => *code,
+            Err(error) => error.exit_code(),
+        }
+    }
+
+    /// completes the report with the outcome of the command, which is its
+    /// exit code on success, prints it with `--output json` and returns
+    /// the exit code
+    pub fn finish(mut self, result: Result<u8, Error>) -> u8 {
+        let code = self.complete(&result);
+        if self.is_json() {
+            // a closed STDOUT must not turn the report into a panic
+            match serde_json::to_string_pretty(&self.report) {
+                Ok(json) => {
+                    let _ = writeln!(std::io::stdout(), "{}", json);
+                }
+                Err(e) => eprintln!("serialization error: {}", e),
+            }
+        } else if let Err(error) = result {
+            eprintln!(
+                "{} {}",
+                console::style("Error:").color256(237),
+                console::style(error.report()).color256(253)
+            );
+        }
+        code
+    }
+}
+
+#[cfg(test)]
+mod output_tests {
+    use crate::errors::Error;
+    use crate::output::{Output, OutputFormat};
+    use crate::errors::IOStage;
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_json_report() -> Result<(), Error> {
+        let mut output = Output::new(OutputFormat::Json, "encrypt file");
+        output.transcoded("in.txt", "out.obg", 3, 32);
+        output.bytes(vec![0xff, 0x00])?;
+        assert_equal!(output.report().files.len(), 2);
+        assert_equal!(output.report().hex, Some("ff00".to_string()));
+
+        let json = serde_json::to_value(output.report()).unwrap();
+        assert_equal!(json["files"][1]["action"], "wrote");
+        assert_equal!(json["files"][1]["bytes"], 32);
+        assert!(json.get("fingerprint").is_none());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_failed_report() {
+        let mut output = Output::new(OutputFormat::Json, "decrypt file");
+        let error = Error::NotEncrypted("in.txt".to_string()).at(IOStage::Accept, "in.txt");
+        assert_equal!(output.complete(&Err(error)), 0x54);
+        assert!(!output.report().ok);
+        assert_equal!(output.report().error.as_ref().unwrap().kind, "NotEncrypted");
+
+        let error = Error::PassphraseError("wrong passphrase".to_string()).at(IOStage::InitCodec, "x");
+        let entry = crate::output::ErrorEntry::from(&error);
+        assert_equal!(entry.kind, "PassphraseError");
+        assert_equal!(entry.stage, Some("InitCodec".to_string()));
+        assert_equal!(entry.exit_code, 5);
+    }
+}
@@ -1,107 +1,598 @@
+use crate::aescbc::check_ciphertext;
 use crate::aescbc::Aes256CbcCodec;
 use crate::aescbc::Aes256Key;
+use crate::aescbc::DerivationScheme;
 use crate::aescbc::EncryptionEngine;
+use crate::aescbc::Padder128;
+use crate::aescbc::SecretArray;
 use crate::ccs::ChaCha20Key;
 use chacha20::cipher::StreamCipher;
-use crate::errors::Error;
+use crate::envelope::{self, WrappedKey, X25519Identity, X25519Recipient};
+use crate::errors::{DecryptionError, Error, StageContext};
+pub use crate::errors::IOStage;
 use crate::ioutils::open_write;
+use crate::passphrase::PassphraseHeader;
+use crate::progress::{Progress, Silent};
 use crate::sneaker;
+use hmac::{Hmac, Mac};
+use sha3::{Digest, Sha3_256};
 use std::fs::File;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Write;
+use zeroize::Zeroizing;
 
-/// Represents the stages of encryption/decryption principally during I/O
-pub enum IOStage {
-    InitCodec,
-    Read,
-    Accept,
-    Metadata,
-    Transcode,
-    Write,
-}
-impl From<IOStage> for String {
-    fn from(stage: IOStage) -> String {
-        String::from(match stage {
-            IOStage::InitCodec => "InitCodec",
-            IOStage::Read => "Read",
-            IOStage::Accept => "Accept",
-            IOStage::Metadata => "Metadata",
-            IOStage::Transcode => "Transcode",
-            IOStage::Write => "Write",
-        })
-    }
-}
-impl From<IOStage> for u8 {
-    fn from(stage: IOStage) -> u8 {
-        u8::from(match stage {
-            IOStage::InitCodec => 0b000001,
-            IOStage::Read => 0b000010,
-            IOStage::Accept => 0b000100,
-            IOStage::Metadata => 0b001000,
-            IOStage::Transcode => 0b010000,
-            IOStage::Write => 0b100000,
-        })
-    }
-}
-
-pub fn decrypt_file(key: Aes256Key, input_file: String, output_file: String) -> Result<(), Error> {
-    let codec = Aes256CbcCodec::new(key.skey(), key.siv());
-    let mut ccs = ChaCha20Key::from_aeskey(&key)?.engine();
-
-    let mut file = File::open(&input_file)?;
-    let ciphertext: Vec<u8> = if sneaker::io::is_snuck(&mut file)? {
-        let mut bytes: Vec<u8> = Vec::new();
-        file.seek(SeekFrom::Start(sneaker::core::MAGIC_WIDTH as u64))?;
-        file.read_to_end(&mut bytes)?;
-        bytes
-    } else {
-        eprintln!(
+/// bytes read, transcoded or written at once, a multiple of the block size
+pub const CHUNK_SIZE: usize = 0x100000;
+
+/// context of the [`check_value`] stored by [`encrypt_file`]
+pub const CHECK_CONTEXT: &[u8] = b"obg-sealed-v1";
+
+/// context of the subkey [`tag`] authenticates containers with
+pub const MAC_CONTEXT: &[u8] = b"obg-mac-v1";
+
+/// width of the [`tag`] which ends checked, envelope and passphrase containers
+pub const TAG_WIDTH: usize = 32;
+
+type HmacSha3 = Hmac<Sha3_256>;
+
+/// applies the chacha20 keystream then encrypts with aes256-cbc
+pub fn seal(key: &Aes256Key, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    seal_with_progress(key, plaintext, &Silent)
+}
+
+/// reverses [`seal`]
+pub fn unseal(key: &Aes256Key, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    unseal_with_progress(key, ciphertext, &Silent)
+}
+
+/// [`seal`] always padding the last block, even a whole one, such that
+/// [`unseal_padded`] can tell malformed padding apart
+pub fn seal_padded(key: &Aes256Key, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    seal_padded_with_progress(key, plaintext, &Silent)
+}
+
+/// reverses [`seal_padded`], failing when the padding is malformed
+pub fn unseal_padded(key: &Aes256Key, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    unseal_padded_with_progress(key, ciphertext, &Silent)
+}
+
+/// value stored next to the payload to recognize the right key
+pub fn check_value(context: &[u8], key: &Aes256Key) -> Result<[u8; 16], Error> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(context);
+    hasher.update(key.skey()?);
+    hasher.update(key.siv()?);
+    let mut check = [0u8; 16];
+    check.copy_from_slice(&hasher.finalize()[..16]);
+    Ok(check)
+}
+
+/// HMAC-SHA3-256 keyed with a subkey of `key` such that the tag
+/// reveals nothing about the key nor its check value
+fn mac(key: &Aes256Key) -> Result<HmacSha3, Error> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(MAC_CONTEXT);
+    hasher.update(key.skey()?);
+    hasher.update(key.siv()?);
+    let subkey = SecretArray::<32>::new(hasher.finalize().into());
+    match HmacSha3::new_from_slice(&subkey[..]) {
+        Ok(mac) => Ok(mac),
+        Err(error) => Err(Error::KeyError(format!("cannot key the tag: {}", error))),
+    }
+}
+
+/// encrypt-then-mac tag of a container: its `header`, everything
+/// before the payload, followed by the `ciphertext` of the payload
+pub fn tag(key: &Aes256Key, header: &[u8], ciphertext: &[u8]) -> Result<[u8; TAG_WIDTH], Error> {
+    let mut mac = mac(key)?;
+    mac.update(header);
+    mac.update(ciphertext);
+    Ok(mac.finalize().into_bytes().into())
+}
+
+/// fails unless `tag` is the [`tag`] of `header` and `ciphertext`,
+/// comparing in constant time
+pub fn check_tag(key: &Aes256Key, header: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<(), Error> {
+    let mut mac = mac(key)?;
+    mac.update(header);
+    mac.update(ciphertext);
+    match mac.verify_slice(tag) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(DecryptionError::new("tag mismatch, the header or ciphertext was modified".to_string()).into()),
+    }
+}
+
+/// [`seal`] chunk by chunk, chaining the cbc blocks across chunks
+pub fn seal_with_progress(
+    key: &Aes256Key,
+    plaintext: &[u8],
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    seal_chunks(key, plaintext, false, progress)
+}
+
+/// [`seal_padded`] chunk by chunk
+pub fn seal_padded_with_progress(
+    key: &Aes256Key,
+    plaintext: &[u8],
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    seal_chunks(key, plaintext, true, progress)
+}
+
+/// [`unseal`] chunk by chunk, the padding is only removed from the last one
+pub fn unseal_with_progress(
+    key: &Aes256Key,
+    ciphertext: &[u8],
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    unseal_chunks(key, ciphertext, false, progress)
+}
+
+/// [`unseal_padded`] chunk by chunk
+pub fn unseal_padded_with_progress(
+    key: &Aes256Key,
+    ciphertext: &[u8],
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    unseal_chunks(key, ciphertext, true, progress)
+}
+
+/// without `padded` only a last block shorter than 16 bytes is padded
+fn seal_chunks(
+    key: &Aes256Key,
+    plaintext: &[u8],
+    padded: bool,
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    let mut codec = Aes256CbcCodec::new_with_key(key)?;
+    let mut iv: SecretArray<16> = Zeroizing::new(*key.siv()?);
+    let mut ccs = ChaCha20Key::from_aeskey(key)?.engine();
+    let mut ciphertext = Vec::with_capacity(plaintext.len() + 16);
+    let count = plaintext.len().div_ceil(CHUNK_SIZE);
+    progress.begin(IOStage::Transcode, plaintext.len() as u64);
+    for (index, chunk) in plaintext.chunks(CHUNK_SIZE).enumerate() {
+        let mut block = Zeroizing::new(chunk.to_vec());
+        ccs.apply_keystream(&mut block);
+        if padded && index + 1 == count {
+            let padding = codec.padding().padding(block.len());
+            block.extend(padding);
+        }
+        let encrypted = codec.encrypt_blocks(&block);
+        iv.copy_from_slice(&encrypted[encrypted.len() - 16..]);
+        codec.set_iv(&iv);
+        ciphertext.extend(encrypted);
+        progress.advance(IOStage::Transcode, chunk.len() as u64);
+    }
+    if padded && count == 0 {
+        ciphertext.extend(codec.encrypt_blocks(&codec.padding().padding(0)));
+    }
+    progress.finish(IOStage::Transcode);
+    Ok(ciphertext)
+}
+
+/// without `padded` a malformed padding is left in the plaintext
+fn unseal_chunks(
+    key: &Aes256Key,
+    ciphertext: &[u8],
+    padded: bool,
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    check_ciphertext(ciphertext)?;
+    if padded && ciphertext.is_empty() {
+        return Err(DecryptionError::new("ciphertext is missing its padding".to_string()).into());
+    }
+    let mut codec = Aes256CbcCodec::new_with_key(key)?;
+    let mut iv: SecretArray<16> = Zeroizing::new(*key.siv()?);
+    let mut ccs = ChaCha20Key::from_aeskey(key)?.engine();
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let count = ciphertext.len().div_ceil(CHUNK_SIZE);
+    progress.begin(IOStage::Transcode, ciphertext.len() as u64);
+    for (index, chunk) in ciphertext.chunks(CHUNK_SIZE).enumerate() {
+        let last = index + 1 == count;
+        codec.set_iv(&iv);
+        let mut block = Zeroizing::new(if last && !padded {
+            codec.decrypt_blocks(chunk)
+        } else {
+            let mut block = Vec::with_capacity(chunk.len());
+            let mut xor_block: &[u8] = &iv[..];
+            for cipherblock in chunk.chunks(16) {
+                block.extend(codec.decrypt_block(cipherblock, xor_block));
+                xor_block = cipherblock;
+            }
+            block
+        });
+        if last && padded {
+            block = Zeroizing::new(codec.padding().strip(&block)?);
+        }
+        iv.copy_from_slice(&chunk[chunk.len() - 16..]);
+        ccs.apply_keystream(&mut block);
+        plaintext.extend_from_slice(&block);
+        progress.advance(IOStage::Transcode, chunk.len() as u64);
+    }
+    progress.finish(IOStage::Transcode);
+    Ok(plaintext)
+}
+
+/// byte counts of a file encryption or decryption
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transcoded {
+    pub read: u64,
+    pub written: u64,
+}
+
+fn transcoded(input: &mut File, output: &mut File) -> Result<Transcoded, Error> {
+    Ok(Transcoded {
+        read: input.stream_position()?,
+        written: output.stream_position()?,
+    })
+}
+
+/// plaintext decrypted in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decrypted {
+    pub plaintext: Vec<u8>,
+    /// bytes read from the encrypted file
+    pub read: u64,
+    /// whether the tag proved the header and ciphertext untouched, which
+    /// only files sealed before obg 4.1 cannot
+    pub authenticated: bool,
+}
+
+fn write_decrypted(decrypted: Decrypted, output_file: &str, progress: &dyn Progress) -> Result<Transcoded, Error> {
+    let mut output = open_write(output_file).at(IOStage::Write, output_file)?;
+    write_chunks(&mut output, &decrypted.plaintext, progress).at(IOStage::Write, output_file)?;
+    Ok(Transcoded {
+        read: decrypted.read,
+        written: output.stream_position().at(IOStage::Write, output_file)?,
+    })
+}
+
+/// reads the rest of `file` reporting the [`IOStage::Read`] stage
+fn read_rest(file: &mut File, progress: &dyn Progress) -> Result<Vec<u8>, Error> {
+    let total = file.metadata()?.len().saturating_sub(file.stream_position()?);
+    let mut bytes = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    progress.begin(IOStage::Read, total);
+    loop {
+        match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(count) => {
+                bytes.extend_from_slice(&chunk[..count]);
+                progress.advance(IOStage::Read, count as u64);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::IOError(e)),
+        }
+    }
+    progress.finish(IOStage::Read);
+    Ok(bytes)
+}
+
+/// writes `bytes` to `file` reporting the [`IOStage::Write`] stage
+fn write_chunks<W: Write>(file: &mut W, bytes: &[u8], progress: &dyn Progress) -> Result<(), Error> {
+    progress.begin(IOStage::Write, bytes.len() as u64);
+    for chunk in bytes.chunks(CHUNK_SIZE) {
+        file.write_all(chunk)?;
+        progress.advance(IOStage::Write, chunk.len() as u64);
+    }
+    progress.finish(IOStage::Write);
+    Ok(())
+}
+
+/// reads the header again, from the start of `file` up to where it was
+/// parsed, and the rest of the container, then checks its tag before
+/// unsealing the payload
+fn unseal_tagged(
+    key: &Aes256Key,
+    file: &mut File,
+    input_file: &str,
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    let mut header = vec![0u8; file.stream_position().at(IOStage::Read, input_file)? as usize];
+    file.rewind().at(IOStage::Read, input_file)?;
+    file.read_exact(&mut header).at(IOStage::Read, input_file)?;
+    let mut ciphertext = read_rest(file, progress).at(IOStage::Read, input_file)?;
+    let tag = match ciphertext.len().checked_sub(TAG_WIDTH) {
+        Some(length) => ciphertext.split_off(length),
+        None => {
+            return Err(Error::from(DecryptionError::new(format!("{} is missing its tag", input_file)))
+                .at(IOStage::Metadata, input_file))
+        }
+    };
+    check_tag(key, &header, &ciphertext, &tag).at(IOStage::Transcode, input_file)?;
+    unseal_padded_with_progress(key, &ciphertext, progress).at(IOStage::Transcode, input_file)
+}
+
+/// writes `header`, the `ciphertext` of the payload and their [`tag`]
+fn write_tagged(
+    key: &Aes256Key,
+    header: &[u8],
+    ciphertext: &[u8],
+    output_file: &str,
+    progress: &dyn Progress,
+) -> Result<File, Error> {
+    let tag = tag(key, header, ciphertext).at(IOStage::Transcode, output_file)?;
+    let mut output = open_write(output_file).at(IOStage::Write, output_file)?;
+    output.write_all(header).at(IOStage::Write, output_file)?;
+    write_chunks(&mut output, ciphertext, progress).at(IOStage::Write, output_file)?;
+    output.write_all(&tag).at(IOStage::Write, output_file)?;
+    Ok(output)
+}
+
+pub fn decrypt_file(
+    key: Aes256Key,
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    let decrypted = decrypt_to_memory(&key, &input_file, progress)?;
+    write_decrypted(decrypted, &output_file, progress)
+}
+
+/// decrypts a file produced by [`encrypt_file`] without writing the
+/// plaintext, the check value proves the key right and the tag the
+/// ciphertext untouched, except for files sealed before obg 4.1 which
+/// carry neither and only tell a wrong key by chance
+pub fn decrypt_to_memory(
+    key: &Aes256Key,
+    input_file: &str,
+    progress: &dyn Progress,
+) -> Result<Decrypted, Error> {
+    let mut file = File::open(input_file).at(IOStage::Read, input_file)?;
+    let checked = sneaker::io::is_checked(&mut file).at(IOStage::Accept, input_file)?;
+    if !checked && !sneaker::io::is_snuck(&mut file).at(IOStage::Accept, input_file)? {
+        return Err(Error::NotEncrypted(format!(
             "{} does not appear to be encrypted with {} {}",
             input_file,
             env!("CARGO_PKG_NAME"),
             env!("CARGO_PKG_VERSION")
+        )));
+    }
+    file.seek(SeekFrom::Start(sneaker::core::MAGIC_WIDTH as u64))
+        .at(IOStage::Read, input_file)?;
+    let plaintext = if checked {
+        let mut check = [0u8; 16];
+        file.read_exact(&mut check).at(IOStage::Metadata, input_file)?;
+        if check_value(CHECK_CONTEXT, key).at(IOStage::InitCodec, input_file)? != check {
+            return Err(Error::from(DecryptionError::new(format!(
+                "{} was encrypted with another key",
+                input_file
+            )))
+            .at(IOStage::InitCodec, input_file));
+        }
+        unseal_tagged(key, &mut file, input_file, progress)?
+    } else {
+        let ciphertext = read_rest(&mut file, progress).at(IOStage::Read, input_file)?;
+        unseal_with_progress(key, &ciphertext, progress).at(IOStage::Transcode, input_file)?
+    };
+    Ok(Decrypted {
+        plaintext,
+        read: file.stream_position().at(IOStage::Read, input_file)?,
+        authenticated: checked,
+    })
+}
+
+pub fn encrypt_file(
+    key: Aes256Key,
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    let mut file = File::open(&input_file).at(IOStage::Read, &input_file)?;
+    if sneaker::io::is_snuck(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_checked(&mut file).at(IOStage::Accept, &input_file)?
+    {
+        return Err(Error::AlreadyEncrypted(input_file));
+    }
+    file.rewind().at(IOStage::Read, &input_file)?;
+    let plaintext = read_rest(&mut file, progress).at(IOStage::Read, &input_file)?;
+    let mut header = sneaker::core::checked_id();
+    header.extend(check_value(CHECK_CONTEXT, &key).at(IOStage::InitCodec, &input_file)?);
+    let ciphertext = seal_padded_with_progress(&key, &plaintext, progress).at(IOStage::Transcode, &input_file)?;
+    let mut output = write_tagged(&key, &header, &ciphertext, &output_file, progress)?;
+    transcoded(&mut file, &mut output).at(IOStage::Write, &output_file)
+}
+
+/// encrypts `input_file` with a random key wrapped to each of the `recipients`
+pub fn encrypt_file_for_recipients(
+    recipients: &[X25519Recipient],
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+        return Err(Error::EnvelopeError(format!(
+            "between 1 and {} recipients are required, got {}",
+            u16::MAX,
+            recipients.len()
+        )));
+    }
+    let mut file = File::open(&input_file).at(IOStage::Read, &input_file)?;
+    if sneaker::io::is_snuck(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_checked(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_enveloped(&mut file).at(IOStage::Accept, &input_file)?
+    {
+        return Err(Error::AlreadyEncrypted(input_file));
+    }
+    file.rewind().at(IOStage::Read, &input_file)?;
+    let plaintext = read_rest(&mut file, progress).at(IOStage::Read, &input_file)?;
+
+    let key = envelope::file_key();
+    let mut header = Vec::<u8>::new();
+    header.extend(sneaker::core::envelope_id());
+    header.extend((recipients.len() as u16).to_be_bytes());
+    for recipient in recipients {
+        header.extend(
+            envelope::wrap_key(&key, recipient)
+                .at(IOStage::InitCodec, &input_file)?
+                .to_bytes(),
         );
-        std::process::exit(0x54);
+    }
+    header.extend(sneaker::core::magic_id());
+    let ciphertext = seal_padded_with_progress(&key, &plaintext, progress).at(IOStage::Transcode, &input_file)?;
+    let mut output = write_tagged(&key, &header, &ciphertext, &output_file, progress)?;
+    transcoded(&mut file, &mut output).at(IOStage::Write, &output_file)
+}
+
+/// decrypts an envelope by trying each wrapped key with `identity`
+pub fn decrypt_file_with_identity(
+    identity: &X25519Identity,
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    let decrypted = decrypt_to_memory_with_identity(identity, &input_file, progress)?;
+    write_decrypted(decrypted, &output_file, progress)
+}
+
+/// [`decrypt_file_with_identity`] without writing the plaintext
+pub fn decrypt_to_memory_with_identity(
+    identity: &X25519Identity,
+    input_file: &str,
+    progress: &dyn Progress,
+) -> Result<Decrypted, Error> {
+    let mut file = File::open(input_file).at(IOStage::Read, input_file)?;
+    if !sneaker::io::is_enveloped(&mut file).at(IOStage::Accept, input_file)? {
+        return Err(Error::NotEncrypted(format!(
+            "{} is not encrypted to x25519 recipients",
+            input_file
+        )));
+    }
+    let mut count = [0u8; 2];
+    file.read_exact(&mut count).at(IOStage::Metadata, input_file)?;
+    let mut key = None;
+    for _ in 0..u16::from_be_bytes(count) {
+        let mut entry = [0u8; envelope::WRAPPED_KEY_WIDTH];
+        file.read_exact(&mut entry).at(IOStage::Metadata, input_file)?;
+        if key.is_none() {
+            let wrapped = WrappedKey::from_bytes(&entry).at(IOStage::Metadata, input_file)?;
+            key = identity.unwrap_key(&wrapped).at(IOStage::InitCodec, input_file)?;
+        }
+    }
+    let key = match key {
+        Some(key) => key,
+        None => {
+            return Err(Error::EnvelopeError(format!(
+                "{} was not encrypted to {}",
+                input_file, identity.public
+            ))
+            .at(IOStage::InitCodec, input_file))
+        }
     };
+    let mut magic = vec![0u8; sneaker::core::MAGIC_WIDTH];
+    file.read_exact(&mut magic).at(IOStage::Metadata, input_file)?;
+    if magic != sneaker::core::magic_id() {
+        return Err(Error::EnvelopeError(format!(
+            "{} has a corrupted envelope payload",
+            input_file
+        ))
+        .at(IOStage::Metadata, input_file));
+    }
+    let plaintext = unseal_tagged(&key, &mut file, input_file, progress)?;
+    Ok(Decrypted {
+        plaintext,
+        read: file.stream_position().at(IOStage::Read, input_file)?,
+        authenticated: true,
+    })
+}
 
-    let mut plaintext = codec.decrypt_blocks(&ciphertext);
-    ccs.apply_keystream(&mut plaintext);
+/// encrypts `input_file` with a key derived from `passphrase` and a
+/// random salt, both the salt and derivation parameters go in the header
+pub fn encrypt_file_with_passphrase(
+    passphrase: &[u8],
+    scheme: DerivationScheme,
+    cycles: u64,
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    let mut file = File::open(&input_file).at(IOStage::Read, &input_file)?;
+    if sneaker::io::is_snuck(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_checked(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_enveloped(&mut file).at(IOStage::Accept, &input_file)?
+        || sneaker::io::is_passphrased(&mut file).at(IOStage::Accept, &input_file)?
+    {
+        return Err(Error::AlreadyEncrypted(input_file));
+    }
+    file.rewind().at(IOStage::Read, &input_file)?;
+    let plaintext = read_rest(&mut file, progress).at(IOStage::Read, &input_file)?;
 
-    let mut file = open_write(&output_file)?;
-    file.write_all(&plaintext)?;
-    eprintln!("wrote {}", output_file);
-    Ok(())
+    let header = PassphraseHeader::new(scheme, cycles).at(IOStage::InitCodec, &input_file)?;
+    let key = header.derive_key(passphrase).at(IOStage::InitCodec, &input_file)?;
+    let mut metadata = sneaker::core::passphrase_id();
+    metadata.extend(header.to_bytes(&key).at(IOStage::Metadata, &input_file)?);
+    metadata.extend(sneaker::core::magic_id());
+    let ciphertext = seal_padded_with_progress(&key, &plaintext, progress).at(IOStage::Transcode, &input_file)?;
+    let mut output = write_tagged(&key, &metadata, &ciphertext, &output_file, progress)?;
+    transcoded(&mut file, &mut output).at(IOStage::Write, &output_file)
 }
 
-pub fn encrypt_file(key: Aes256Key, input_file: String, output_file: String) -> Result<(), Error> {
-    let codec = Aes256CbcCodec::new(key.skey(), key.siv());
-    let mut ccs = ChaCha20Key::from_aeskey(&key)?.engine();
-    let mut file = File::open(&input_file)?;
-    if sneaker::io::is_snuck(&mut file)? {
-        eprintln!("already encrypted: {}", input_file);
-        std::process::exit(0x54);
-    }
-    file.rewind()?;
-    let mut plaintext = Vec::new();
-    file.read_to_end(&mut plaintext)?;
-    let mut ciphertext = plaintext.clone();
-    ccs.apply_keystream(&mut ciphertext);
-    let ciphertext = codec.encrypt_blocks(&ciphertext);
-    let mut file = open_write(&output_file)?;
-    file.write_all(&sneaker::core::magic_id())?;
-    file.write_all(&ciphertext)?;
-    eprintln!("wrote {}", output_file);
-    Ok(())
+/// decrypts a file produced by [`encrypt_file_with_passphrase`]
+pub fn decrypt_file_with_passphrase(
+    passphrase: &[u8],
+    input_file: String,
+    output_file: String,
+    progress: &dyn Progress,
+) -> Result<Transcoded, Error> {
+    let decrypted = decrypt_to_memory_with_passphrase(passphrase, &input_file, progress)?;
+    write_decrypted(decrypted, &output_file, progress)
+}
+
+/// [`decrypt_file_with_passphrase`] without writing the plaintext
+pub fn decrypt_to_memory_with_passphrase(
+    passphrase: &[u8],
+    input_file: &str,
+    progress: &dyn Progress,
+) -> Result<Decrypted, Error> {
+    let mut file = File::open(input_file).at(IOStage::Read, input_file)?;
+    if !sneaker::io::is_passphrased(&mut file).at(IOStage::Accept, input_file)? {
+        return Err(Error::NotEncrypted(format!(
+            "{} is not encrypted with a passphrase",
+            input_file
+        )));
+    }
+    let (header, check) = PassphraseHeader::read(&mut file).at(IOStage::Metadata, input_file)?;
+    let key = header.unlock(passphrase, &check).at(IOStage::InitCodec, input_file)?;
+    let mut magic = vec![0u8; sneaker::core::MAGIC_WIDTH];
+    file.read_exact(&mut magic).at(IOStage::Metadata, input_file)?;
+    if magic != sneaker::core::magic_id() {
+        return Err(Error::PassphraseError(format!(
+            "{} has a corrupted payload",
+            input_file
+        ))
+        .at(IOStage::Metadata, input_file));
+    }
+    let plaintext = unseal_tagged(&key, &mut file, input_file, progress)?;
+    Ok(Decrypted {
+        plaintext,
+        read: file.stream_position().at(IOStage::Read, input_file)?,
+        authenticated: true,
+    })
 }
 
 #[cfg(test)]
 mod pap_tests {
     use crate::aescbc::cdc::Aes256Key;
+    use crate::aescbc::config::Pbkdf2HashingAlgo;
+    use crate::aescbc::kd::DerivationScheme;
     use crate::emit::TempEmission;
     use crate::errors::Error;
-    use crate::pap::{decrypt_file, encrypt_file};
+    use crate::envelope::X25519Identity;
+    use crate::pap::{
+        decrypt_file, decrypt_file_with_identity, decrypt_file_with_passphrase, encrypt_file,
+        encrypt_file_for_recipients, encrypt_file_with_passphrase, IOStage,
+    };
+    use crate::pap::{decrypt_to_memory, decrypt_to_memory_with_identity, decrypt_to_memory_with_passphrase, Decrypted};
+    use crate::progress::{Progress, Silent};
+    use crate::aescbc::{Aes256CbcCodec, EncryptionEngine};
+    use crate::ccs::ChaCha20Key;
+    use crate::pap::{seal, seal_padded, unseal, unseal_padded, CHUNK_SIZE, TAG_WIDTH};
+    use crate::sneaker;
+    use chacha20::cipher::StreamCipher;
+    use std::cell::RefCell;
 
     use k9::assert_equal;
     use std::fs::{read, File};
@@ -139,6 +630,9 @@ mod pap_tests {
         ];
         Aes256Key::new(key, iv, &blob, 0)
     }
+    fn other_key() -> Aes256Key {
+        Aes256Key::new([0x47; 32], [0x54; 16], &[0x35; 36], 0)
+    }
     #[test]
     pub fn test_e2e_sequential_bytes() -> Result<(), Error> {
         let emission = TempEmission::now();
@@ -149,8 +643,8 @@ mod pap_tests {
         file.write_all(&bytes)?;
         let enpath = format!("{}.en", path);
         let depath = format!("{}.de", path);
-        encrypt_file(key.clone(), path, enpath.clone())?;
-        decrypt_file(key.clone(), enpath.clone(), depath.clone())?;
+        encrypt_file(key.clone(), path, enpath.clone(), &Silent)?;
+        decrypt_file(key.clone(), enpath.clone(), depath.clone(), &Silent)?;
         // let enbytes = read(enpath)?;
         let debytes = read(depath)?;
         assert_equal!(bytes, debytes);
@@ -167,11 +661,239 @@ mod pap_tests {
         file.write_all(&bytes)?;
         let enpath = format!("{}.en", path);
         let depath = format!("{}.de", path);
-        encrypt_file(key.clone(), path, enpath.clone())?;
-        decrypt_file(key.clone(), enpath.clone(), depath.clone())?;
+        encrypt_file(key.clone(), path, enpath.clone(), &Silent)?;
+        decrypt_file(key.clone(), enpath.clone(), depath.clone(), &Silent)?;
         // let enbytes = read(enpath)?;
         let debytes = read(depath)?;
         assert_equal!(bytes, debytes);
         Ok(())
     }
+    #[test]
+    pub fn test_e2e_x25519_recipients() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let alice = X25519Identity::generate();
+        let bob = X25519Identity::generate();
+        let eve = X25519Identity::generate();
+        let path = format!("{}", path.display());
+        let bytes = random_bytes(71)?;
+        file.write_all(&bytes)?;
+        let enpath = format!("{}.en", path);
+        let depath = format!("{}.de", path);
+        encrypt_file_for_recipients(&[alice.recipient(), bob.recipient()], path, enpath.clone(), &Silent)?;
+        decrypt_file_with_identity(&bob, enpath.clone(), depath.clone(), &Silent)?;
+        assert_equal!(bytes, read(&depath)?);
+        assert!(decrypt_file_with_identity(&eve, enpath.clone(), depath.clone(), &Silent).is_err());
+        Ok(())
+    }
+    #[test]
+    pub fn test_e2e_passphrase() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let path = format!("{}", path.display());
+        let bytes = random_bytes(73)?;
+        file.write_all(&bytes)?;
+        let enpath = format!("{}.en", path);
+        let depath = format!("{}.de", path);
+        let scheme = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_384);
+        encrypt_file_with_passphrase(b"hogwarts", scheme, 37, path, enpath.clone(), &Silent)?;
+        decrypt_file_with_passphrase(b"hogwarts", enpath.clone(), depath.clone(), &Silent)?;
+        assert_equal!(bytes, read(&depath)?);
+        assert!(decrypt_file_with_passphrase(b"durmstrang", enpath.clone(), depath.clone(), &Silent).is_err());
+        Ok(())
+    }
+    #[test]
+    pub fn test_encryption_state_errors() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let key = get_key();
+        let path = format!("{}", path.display());
+        file.write_all(&seq_bytes(64)?)?;
+        let enpath = format!("{}.en", path);
+        let depath = format!("{}.de", path);
+        assert!(matches!(
+            decrypt_file(key.clone(), path.clone(), depath.clone(), &Silent),
+            Err(Error::NotEncrypted(_))
+        ));
+        encrypt_file(key.clone(), path, enpath.clone(), &Silent)?;
+        assert!(matches!(
+            encrypt_file(key.clone(), enpath.clone(), depath.clone(), &Silent),
+            Err(Error::AlreadyEncrypted(_))
+        ));
+        Ok(())
+    }
+    #[test]
+    pub fn test_errors_carry_stage() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let key = get_key();
+        let path = format!("{}", path.display());
+        file.write_all(&seq_bytes(64)?)?;
+        let enpath = format!("{}.en", path);
+        let depath = format!("{}.de", path);
+
+        let error = decrypt_file(key.clone(), format!("{}.missing", path), depath.clone(), &Silent).unwrap_err();
+        assert_equal!(error.stage(), Some(IOStage::Read));
+        assert!(matches!(error.root(), Error::IOError(_)));
+        assert!(std::error::Error::source(&error).is_some());
+
+        encrypt_file(key.clone(), path, enpath.clone(), &Silent)?;
+        let mut truncated = read(&enpath)?;
+        truncated.pop();
+        File::create(&enpath)?.write_all(&truncated)?;
+        let error = decrypt_file(key.clone(), enpath.clone(), depath.clone(), &Silent).unwrap_err();
+        assert_equal!(error.stage(), Some(IOStage::Transcode));
+        assert!(matches!(error.root(), Error::DecryptionError(_)));
+        assert_equal!(error.exit_code(), crate::errors::exit::DECRYPTION);
+        assert!(error.report().starts_with(&format!("failed at Transcode stage on {}: ", enpath)));
+        Ok(())
+    }
+    #[test]
+    pub fn test_seal_chains_chunks() -> Result<(), Error> {
+        let key = get_key();
+        let plaintext = seq_bytes(2 * CHUNK_SIZE + 7)?;
+        let mut expected = plaintext.clone();
+        ChaCha20Key::from_aeskey(&key)?.engine().apply_keystream(&mut expected);
+        let expected = Aes256CbcCodec::new_with_key(&key)?.encrypt_blocks(&expected);
+        let ciphertext = seal(&key, &plaintext)?;
+        assert_equal!(ciphertext, expected);
+        assert_equal!(unseal(&key, &ciphertext)?, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_seal_padded() -> Result<(), Error> {
+        let key = get_key();
+        for length in [0, 7, 16, CHUNK_SIZE, 2 * CHUNK_SIZE + 7] {
+            let plaintext = seq_bytes(length)?;
+            let ciphertext = seal_padded(&key, &plaintext)?;
+            assert_equal!(ciphertext.len(), (length / 16 + 1) * 16);
+            assert_equal!(unseal_padded(&key, &ciphertext)?, plaintext);
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_unseal_padded_refuses_malformed_padding() -> Result<(), Error> {
+        let key = get_key();
+        let ciphertext = seal_padded(&key, &seq_bytes(32)?)?;
+        let error = unseal_padded(&other_key(), &ciphertext).unwrap_err();
+        assert!(matches!(error, Error::DecryptionError(_)));
+        assert!(unseal_padded(&key, &ciphertext[..32]).is_err());
+        assert!(unseal_padded(&key, &[]).is_err());
+        Ok(())
+    }
+
+    struct Recorder(RefCell<Vec<(IOStage, u64)>>);
+    impl Progress for Recorder {
+        fn advance(&self, stage: IOStage, bytes: u64) {
+            self.0.borrow_mut().push((stage, bytes));
+        }
+    }
+    impl Recorder {
+        fn total(&self, stage: IOStage) -> u64 {
+            self.0.borrow().iter().filter(|(s, _)| *s == stage).map(|(_, b)| b).sum()
+        }
+    }
+
+    #[test]
+    pub fn test_progress_per_stage() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let key = get_key();
+        let path = format!("{}", path.display());
+        file.write_all(&seq_bytes(CHUNK_SIZE + 3)?)?;
+        let enpath = format!("{}.en", path);
+        let recorder = Recorder(RefCell::new(Vec::new()));
+        let transcoded = encrypt_file(key.clone(), path, enpath.clone(), &recorder)?;
+        assert_equal!(transcoded.read, (CHUNK_SIZE + 3) as u64);
+        assert_equal!(transcoded.written, read(&enpath)?.len() as u64);
+        assert_equal!(recorder.total(IOStage::Read), (CHUNK_SIZE + 3) as u64);
+        assert_equal!(recorder.total(IOStage::Transcode), (CHUNK_SIZE + 3) as u64);
+        assert_equal!(recorder.total(IOStage::Write), (CHUNK_SIZE + 16) as u64);
+        assert_equal!(recorder.0.borrow().len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_decrypt_to_memory() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let key = get_key();
+        let path = format!("{}", path.display());
+        let bytes = seq_bytes(75)?;
+        file.write_all(&bytes)?;
+        let enpath = format!("{}.en", path);
+        let pppath = format!("{}.pp", path);
+        encrypt_file(key.clone(), path.clone(), enpath.clone(), &Silent)?;
+        let scheme = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256);
+        encrypt_file_with_passphrase(b"hogwarts", scheme, 37, path.clone(), pppath.clone(), &Silent)?;
+
+        let decrypted = decrypt_to_memory(&key, &enpath, &Silent)?;
+        assert_equal!(decrypted.plaintext, bytes);
+        assert_equal!(decrypted.read, read(&enpath)?.len() as u64);
+        assert!(decrypted.authenticated);
+        let error = decrypt_to_memory(&other_key(), &enpath, &Silent).unwrap_err();
+        assert_equal!(error.stage(), Some(IOStage::InitCodec));
+        assert_equal!(error.exit_code(), crate::errors::exit::DECRYPTION);
+
+        // files written before the check value are decrypted without proving the key
+        let legacy = format!("{}.legacy", path);
+        let mut sealed = sneaker::core::magic_id();
+        sealed.extend(seal(&key, &bytes)?);
+        File::create(&legacy)?.write_all(&sealed)?;
+        let decrypted = decrypt_to_memory(&key, &legacy, &Silent)?;
+        assert_equal!(decrypted.plaintext, bytes);
+        assert!(!decrypted.authenticated);
+
+        let decrypted = decrypt_to_memory_with_passphrase(b"hogwarts", &pppath, &Silent)?;
+        assert_equal!(decrypted.plaintext, bytes);
+        assert!(decrypted.authenticated);
+        assert!(decrypt_to_memory_with_passphrase(b"durmstrang", &pppath, &Silent).is_err());
+        assert!(matches!(
+            decrypt_to_memory_with_passphrase(b"hogwarts", &enpath, &Silent),
+            Err(Error::NotEncrypted(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_tag_refuses_tampering() -> Result<(), Error> {
+        let emission = TempEmission::now();
+        let (mut file, path) = emission.papobg_8473776564_file()?;
+        let key = get_key();
+        let alice = X25519Identity::generate();
+        let path = format!("{}", path.display());
+        let bytes = seq_bytes(3 * CHUNK_SIZE / 2)?;
+        file.write_all(&bytes)?;
+        let (enpath, x25519path, pppath) = (format!("{}.en", path), format!("{}.x25519", path), format!("{}.pp", path));
+        encrypt_file(key.clone(), path.clone(), enpath.clone(), &Silent)?;
+        encrypt_file_for_recipients(&[alice.recipient()], path.clone(), x25519path.clone(), &Silent)?;
+        let scheme = DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256);
+        encrypt_file_with_passphrase(b"hogwarts", scheme, 37, path, pppath.clone(), &Silent)?;
+        type Decrypt<'a> = &'a dyn Fn(&str) -> Result<Decrypted, Error>;
+        let decrypt: [(&str, Decrypt); 3] = [
+            (&enpath, &|path| decrypt_to_memory(&key, path, &Silent)),
+            (&x25519path, &|path| decrypt_to_memory_with_identity(&alice, path, &Silent)),
+            (&pppath, &|path| decrypt_to_memory_with_passphrase(b"hogwarts", path, &Silent)),
+        ];
+        for (path, decrypt) in decrypt {
+            let decrypted = decrypt(path)?;
+            assert_equal!(decrypted.plaintext, bytes);
+            assert!(decrypted.authenticated);
+
+            let original = read(path)?;
+            for position in [original.len() / 2, original.len() - 1] {
+                let mut tampered = original.clone();
+                tampered[position] ^= 0x01;
+                File::create(path)?.write_all(&tampered)?;
+                let error = decrypt(path).unwrap_err();
+                assert_equal!(error.stage(), Some(IOStage::Transcode));
+                assert_equal!(error.exit_code(), crate::errors::exit::DECRYPTION);
+            }
+            File::create(path)?.write_all(&original[..original.len() - TAG_WIDTH])?;
+            assert!(decrypt(path).is_err());
+        }
+        Ok(())
+    }
 }
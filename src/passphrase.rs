@@ -0,0 +1,157 @@
+/*!
+Passphrase Headers
+
+Files encrypted with a passphrase rather than a key file carry the
+parameters needed to derive the key again: the derivation scheme,
+the cycles, a random salt and a check value which tells a wrong
+passphrase apart from a corrupted file.
+
+```text
+passphrase_id | scheme (u8) | cycles (u32 BE) | salt length (u8) | salt | check (16 bytes)
+```
+
+The sealed payload and the tag of [`crate::pap::tag`] follow it.
+*/
+use crate::aescbc::cdc::Aes256Key;
+use crate::aescbc::config::{IvDerivation, Pbkdf2Config};
+use crate::aescbc::kd::DerivationScheme;
+use crate::errors::Error;
+use crate::pap;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::io::Read;
+
+pub const CHECK_CONTEXT: &[u8] = b"obg-passphrase-v1";
+pub const SALT_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassphraseHeader {
+    pub scheme: DerivationScheme,
+    pub cycles: u32,
+    pub salt: Vec<u8>,
+}
+
+impl PassphraseHeader {
+    /// parameters with a fresh random salt
+    pub fn new(scheme: DerivationScheme, cycles: u64) -> Result<PassphraseHeader, Error> {
+        if !scheme.is_stretching() {
+            return Err(Error::PassphraseError(format!(
+                "{} does not stretch passphrases, use a pbkdf2 scheme",
+                scheme
+            )));
+        }
+        let cycles = match u32::try_from(cycles) {
+            Ok(cycles) if cycles > 0 => cycles,
+            _ => {
+                return Err(Error::PassphraseError(format!(
+                    "cycles must be between 1 and {}, got {}",
+                    u32::MAX,
+                    cycles
+                )))
+            }
+        };
+        let mut salt = vec![0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        Ok(PassphraseHeader {
+            scheme,
+            cycles,
+            salt,
+        })
+    }
+
+    /// the key of the file, whose random salt already makes it unique
+    /// such that the legacy iv keeps the files of obg 4.1 readable
+    pub fn derive_key(&self, passphrase: &[u8]) -> Result<Aes256Key, Error> {
+        let derivation = Pbkdf2Config::new(self.scheme.clone(), self.cycles as u64, false, false)
+            .with_iv_derivation(IvDerivation::Legacy);
+        Aes256Key::derive_from_bytes(passphrase, &self.salt, &derivation, None)
+    }
+
+    /// value stored next to the parameters to recognize the right key
+    pub fn check_value(key: &Aes256Key) -> Result<[u8; 16], Error> {
+        pap::check_value(CHECK_CONTEXT, key)
+    }
+
+    /// serializes the parameters followed by the check value of `key`
+    pub fn to_bytes(&self, key: &Aes256Key) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(6 + self.salt.len() + 16);
+        bytes.push(self.scheme.id());
+        bytes.extend(self.cycles.to_be_bytes());
+        bytes.push(self.salt.len() as u8);
+        bytes.extend(&self.salt);
+        bytes.extend(Self::check_value(key)?);
+        Ok(bytes)
+    }
+
+    /// reads the parameters and check value which follow the magic
+    pub fn read<R: Read>(source: &mut R) -> Result<(PassphraseHeader, [u8; 16]), Error> {
+        let mut scheme = [0u8; 1];
+        source.read_exact(&mut scheme)?;
+        let scheme = match DerivationScheme::from_id(scheme[0]) {
+            Some(scheme) => scheme,
+            None => {
+                return Err(Error::PassphraseError(format!(
+                    "unknown derivation scheme 0x{:02x}",
+                    scheme[0]
+                )))
+            }
+        };
+        let mut cycles = [0u8; 4];
+        source.read_exact(&mut cycles)?;
+        let mut length = [0u8; 1];
+        source.read_exact(&mut length)?;
+        let mut salt = vec![0u8; length[0] as usize];
+        source.read_exact(&mut salt)?;
+        let mut check = [0u8; 16];
+        source.read_exact(&mut check)?;
+        Ok((
+            PassphraseHeader {
+                scheme,
+                cycles: u32::from_be_bytes(cycles),
+                salt,
+            },
+            check,
+        ))
+    }
+
+    /// derives the key and verifies it against `check`
+    pub fn unlock(&self, passphrase: &[u8], check: &[u8; 16]) -> Result<Aes256Key, Error> {
+        let key = self.derive_key(passphrase)?;
+        if &Self::check_value(&key)? != check {
+            return Err(Error::PassphraseError("wrong passphrase".to_string()));
+        }
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod passphrase_tests {
+    use crate::aescbc::config::Pbkdf2HashingAlgo;
+    use crate::aescbc::kd::DerivationScheme;
+    use crate::errors::Error;
+    use crate::hashis::CrcAlgo;
+    use crate::passphrase::PassphraseHeader;
+    use k9::assert_equal;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn test_header_roundtrip() -> Result<(), Error> {
+        let header = PassphraseHeader::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256), 37)?;
+        let key = header.derive_key(b"hogwarts")?;
+        let (parsed, check) = PassphraseHeader::read(&mut Cursor::new(header.to_bytes(&key)?))?;
+        assert_equal!(parsed, header);
+
+        let unlocked = parsed.unlock(b"hogwarts", &check)?;
+        assert_equal!(unlocked.skey()?, key.skey()?);
+        assert_equal!(unlocked.siv()?, key.siv()?);
+        assert!(parsed.unlock(b"durmstrang", &check).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_rejects_weak_parameters() -> Result<(), Error> {
+        assert!(PassphraseHeader::new(DerivationScheme::Crc(CrcAlgo::GcRc256), 37).is_err());
+        assert!(PassphraseHeader::new(DerivationScheme::Pbkdf2(Pbkdf2HashingAlgo::Sha3_256), 0).is_err());
+        Ok(())
+    }
+}
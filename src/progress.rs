@@ -0,0 +1,81 @@
+/*!
+Progress Reporting
+
+The file functions of [`crate::pap`] report how many bytes each
+[`IOStage`] processed so callers can render progress of large inputs.
+*/
+use crate::errors::Error;
+use crate::errors::IOStage;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::cell::RefCell;
+
+/// inputs below this size finish too fast for bars to be of any use
+pub const LARGE_INPUT: u64 = 0x400000;
+
+/// receives the bytes processed at each [`IOStage`]
+pub trait Progress {
+    /// `stage` is about to process `total` bytes
+    fn begin(&self, _stage: IOStage, _total: u64) {}
+    /// `stage` processed `bytes` more bytes
+    fn advance(&self, stage: IOStage, bytes: u64);
+    /// `stage` processed all of its bytes
+    fn finish(&self, _stage: IOStage) {}
+}
+
+/// ignores all progress
+pub struct Silent;
+
+impl Progress for Silent {
+    fn advance(&self, _stage: IOStage, _bytes: u64) {}
+}
+
+/// renders a bar with ETA on stderr for each stage
+pub struct Bars {
+    style: ProgressStyle,
+    bar: RefCell<Option<ProgressBar>>,
+}
+
+impl Bars {
+    pub fn new() -> Result<Bars, Error> {
+        Ok(Bars {
+            style: ProgressStyle::with_template(
+                "{msg:<9} [{elapsed_precise}] {bar:40.255} {bytes}/{total_bytes} eta {eta}",
+            )?,
+            bar: RefCell::new(None),
+        })
+    }
+
+    /// bars for `path` unless `quiet`, stderr is not a terminal or the
+    /// input is smaller than [`LARGE_INPUT`]
+    pub fn for_file(path: &str, quiet: bool) -> Result<Box<dyn Progress>, Error> {
+        let large = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len() >= LARGE_INPUT,
+            Err(_) => false,
+        };
+        if quiet || !large || !console::Term::stderr().is_term() {
+            return Ok(Box::new(Silent));
+        }
+        Ok(Box::new(Bars::new()?))
+    }
+}
+
+impl Progress for Bars {
+    fn begin(&self, stage: IOStage, total: u64) {
+        let bar = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr())
+            .with_style(self.style.clone())
+            .with_message(String::from(stage));
+        if let Some(previous) = self.bar.replace(Some(bar)) {
+            previous.finish_and_clear();
+        }
+    }
+    fn advance(&self, _stage: IOStage, bytes: u64) {
+        if let Some(bar) = self.bar.borrow().as_ref() {
+            bar.inc(bytes);
+        }
+    }
+    fn finish(&self, _stage: IOStage) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,189 @@
+/*!
+Interactive secret entry
+
+Secrets are read without echo from the controlling terminal or, when
+configured, from an external `pinentry` program spoken to over the
+Assuan protocol which suits GUI sessions and `ssh -t`.
+*/
+use crate::aescbc::SecretBytes;
+use crate::errors::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{ChildStdin, Command, Stdio};
+use zeroize::Zeroizing;
+
+/// reads a secret without echo, asks it twice when `confirm` is true
+pub fn read_secret(description: &str, confirm: bool, pinentry: Option<&str>) -> Result<SecretBytes, Error> {
+    let secret = match pinentry {
+        Some(program) => Zeroizing::new(Pinentry::new(program).get_pin(description, confirm)?),
+        None => {
+            let secret = Zeroizing::new(rpassword::prompt_password(format!("{}: ", description))?);
+            if confirm {
+                let repeated = Zeroizing::new(rpassword::prompt_password(format!("{} (again): ", description))?);
+                if repeated != secret {
+                    return Err(Error::PromptError(format!("{} entries do not match", description)));
+                }
+            }
+            secret
+        }
+    };
+    if secret.is_empty() {
+        return Err(Error::PromptError(format!("{} is empty", description)));
+    }
+    Ok(SecretBytes::from(secret.as_bytes()))
+}
+
+/// client of a `pinentry` program
+#[derive(Debug, Clone)]
+pub struct Pinentry {
+    program: String,
+}
+
+impl Pinentry {
+    pub fn new(program: &str) -> Pinentry {
+        Pinentry {
+            program: program.to_string(),
+        }
+    }
+
+    /// asks the pin described by `description`, pinentry itself
+    /// verifies both entries match when `confirm` is true
+    pub fn get_pin(&self, description: &str, confirm: bool) -> Result<String, Error> {
+        let mut child = match Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                return Err(Error::PromptError(format!(
+                    "cannot run {}: {}",
+                    self.program, e
+                )))
+            }
+        };
+        let (mut stdin, mut stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, BufReader::new(stdout)),
+            _ => return Err(Error::PromptError(format!("cannot talk to {}", self.program))),
+        };
+
+        let result = (|| -> Result<String, Error> {
+            assuan_response(&mut stdout)?;
+            if let Ok(tty) = std::env::var("GPG_TTY") {
+                assuan_command(&mut stdin, &mut stdout, &format!("OPTION ttyname={}", tty))?;
+            }
+            assuan_command(&mut stdin, &mut stdout, "SETTITLE obg")?;
+            assuan_command(&mut stdin, &mut stdout, &format!("SETDESC {}", assuan_encode(description)))?;
+            assuan_command(&mut stdin, &mut stdout, &format!("SETPROMPT {}:", assuan_encode(description)))?;
+            if confirm {
+                assuan_command(&mut stdin, &mut stdout, "SETREPEAT again")?;
+                assuan_command(&mut stdin, &mut stdout, "SETREPEATERROR entries do not match")?;
+            }
+            let lines = assuan_command(&mut stdin, &mut stdout, "GETPIN")?;
+            let mut pin = String::new();
+            for line in lines.iter() {
+                if let Some(data) = line.strip_prefix("D ") {
+                    pin.push_str(&assuan_decode(data)?);
+                }
+            }
+            Ok(pin)
+        })();
+        let _ = writeln!(stdin, "BYE");
+        drop(stdin);
+        let _ = child.wait();
+        result
+    }
+}
+
+fn assuan_command<R: BufRead>(stdin: &mut ChildStdin, stdout: &mut R, command: &str) -> Result<Vec<String>, Error> {
+    writeln!(stdin, "{}", command)?;
+    stdin.flush()?;
+    assuan_response(stdout)
+}
+
+/// collects the lines up to and including `OK`, fails on `ERR`
+pub fn assuan_response<R: BufRead>(stdout: &mut R) -> Result<Vec<String>, Error> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if stdout.read_line(&mut line)? == 0 {
+            return Err(Error::PromptError("pinentry closed the connection".to_string()));
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line == "OK" || line.starts_with("OK ") {
+            return Ok(lines);
+        }
+        if let Some(error) = line.strip_prefix("ERR ") {
+            return Err(Error::PromptError(format!("pinentry: {}", error)));
+        }
+        lines.push(line);
+    }
+}
+
+/// percent-escapes the characters reserved by the Assuan protocol
+pub fn assuan_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            '\n' => encoded.push_str("%0A"),
+            '\r' => encoded.push_str("%0D"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// reverses the percent-escaping of Assuan data lines
+pub fn assuan_decode(value: &str) -> Result<String, Error> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = match value.get(index + 1..index + 3) {
+                Some(hex) => hex,
+                None => return Err(Error::PromptError(format!("truncated escape in {:?}", value))),
+            };
+            decoded.push(u8::from_str_radix(hex, 16)?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    Ok(String::from_utf8(decoded)?)
+}
+
+#[cfg(test)]
+mod prompt_tests {
+    use crate::errors::Error;
+    use crate::prompt::{assuan_decode, assuan_encode, assuan_response, Pinentry};
+    use k9::assert_equal;
+    use std::io::Cursor;
+
+    #[test]
+    pub fn test_assuan_escaping() -> Result<(), Error> {
+        assert_equal!(assuan_encode("100%\nsure"), "100%25%0Asure");
+        assert_equal!(assuan_decode("100%25%0Asure")?, "100%\nsure");
+        assert!(assuan_decode("broken%2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_assuan_response() -> Result<(), Error> {
+        let mut ok = Cursor::new(b"S PIN_REPEATED\nD s%25cret\nOK\n".to_vec());
+        assert_equal!(
+            assuan_response(&mut ok)?,
+            vec!["S PIN_REPEATED".to_string(), "D s%25cret".to_string()]
+        );
+        let mut cancelled = Cursor::new(b"ERR 83886179 Operation cancelled\n".to_vec());
+        assert!(assuan_response(&mut cancelled).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_missing_pinentry() -> Result<(), Error> {
+        assert!(Pinentry::new("/nonexistent/pinentry").get_pin("password", false).is_err());
+        Ok(())
+    }
+}
@@ -1,93 +1,233 @@
+/*!
+Serialization Formats
+
+Configs and keys are persisted as yaml, json or toml. Files are written
+in the format given by their extension, or by a flag, and read in the
+format of their extension or else in the format their contents appear
+to be in, such that tooling may emit whichever it prefers.
+*/
 use crate::errors::Error;
-use crate::errors::YamlFileError;
-// use crate::errors::YamlFileError;
+use crate::errors::{Cause, YamlFileError};
 use crate::ioutils::{absolute_path, open_write};
 
+use clap::ValueEnum;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use std::fmt;
+use std::path::Path;
 use std::{
     fs::{self},
     io::Write,
 };
 
-pub trait YamlFile {
-    fn from_yaml<'a>(data: String) -> Result<Self, Error>
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum FileFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                FileFormat::Yaml => "yaml",
+                FileFormat::Json => "json",
+                FileFormat::Toml => "toml",
+            }
+        )
+    }
+}
+
+impl FileFormat {
+    /// the format of `path` going by its extension
+    pub fn from_extension(path: &str) -> Option<FileFormat> {
+        let extension = Path::new(path).extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "yml" | "yaml" => Some(FileFormat::Yaml),
+            "json" => Some(FileFormat::Json),
+            "toml" => Some(FileFormat::Toml),
+            _ => None,
+        }
+    }
+    /// the extension of files written in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::Yaml => "yml",
+            FileFormat::Json => "json",
+            FileFormat::Toml => "toml",
+        }
+    }
+    /// the format `text` appears to be in: json or toml when it parses
+    /// as such, yaml otherwise
+    pub fn sniff(text: &str) -> FileFormat {
+        if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+            FileFormat::Json
+        } else if !text.trim().is_empty() && text.parse::<toml::Table>().is_ok() {
+            FileFormat::Toml
+        } else {
+            FileFormat::Yaml
+        }
+    }
+    /// the format to write `path` in: that of its extension, else that of
+    /// its current contents, else yaml
+    pub fn for_path(path: &str) -> FileFormat {
+        if let Some(format) = FileFormat::from_extension(path) {
+            return format;
+        }
+        match fs::read_to_string(absolute_path(path)) {
+            Ok(text) => FileFormat::sniff(&text),
+            Err(_) => FileFormat::Yaml,
+        }
+    }
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T, format: FileFormat) -> Result<String, Error> {
+    let result: Result<String, Cause> = match format {
+        FileFormat::Yaml => serde_yaml::to_string(value).map_err(Cause::from),
+        FileFormat::Json => match serde_json::to_string_pretty(value) {
+            Ok(json) => Ok(format!("{}\n", json)),
+            Err(e) => Err(e.into()),
+        },
+        FileFormat::Toml => toml::to_string(value).map_err(Cause::from),
+    };
+    match result {
+        Ok(text) => Ok(text),
+        Err(error) => Err(YamlFileError::with_source(format!("failed to encode {}", format), error).into()),
+    }
+}
+
+pub fn from_str<T: DeserializeOwned>(text: &str, format: FileFormat) -> Result<T, Error> {
+    let result: Result<T, Cause> = match format {
+        FileFormat::Yaml => serde_yaml::from_str(text).map_err(Cause::from),
+        FileFormat::Json => serde_json::from_str(text).map_err(Cause::from),
+        FileFormat::Toml => toml::from_str(text).map_err(Cause::from),
+    };
+    match result {
+        Ok(value) => Ok(value),
+        Err(error) => Err(YamlFileError::with_source(format!("failed to deserialize {}", format), error).into()),
+    }
+}
+
+/// reads `filename` in the format of its extension or else in the
+/// format its contents appear to be in
+pub fn load<T: DeserializeOwned>(filename: &str) -> Result<T, Error> {
+    let filename = absolute_path(filename);
+    let text = match fs::read_to_string(filename.as_str()) {
+        Ok(text) => text,
+        Err(error) => {
+            return Err(YamlFileError::with_source(format!("failed to read file {}", filename), error).into())
+        }
+    };
+    let format = FileFormat::from_extension(&filename).unwrap_or_else(|| FileFormat::sniff(&text));
+    from_str(&text, format)
+}
+
+/// writes `value` to `filename` in `format` and returns the absolute filename
+pub fn save<T: Serialize + ?Sized>(value: &T, filename: &str, format: FileFormat) -> Result<String, Error> {
+    let filename = absolute_path(filename);
+    let text = to_string(value, format)?;
+    let mut file = match open_write(filename.as_str()) {
+        Ok(file) => file,
+        Err(error) => {
+            return Err(YamlFileError::with_source(format!("failed to create file {}", filename), error).into())
+        }
+    };
+    if let Err(error) = file.write_all(text.as_ref()) {
+        return Err(YamlFileError::with_source(format!("failed to write file {}", filename), error).into());
+    }
+    Ok(filename)
+}
+
+pub trait ConfigFile {
+    fn from_str_as(data: &str, format: FileFormat) -> Result<Self, Error>
     where
         Self: DeserializeOwned,
-        Self: Clone,
-        Self: PartialEq,
     {
-        let cfg: Self = match serde_yaml::from_str(&data) {
-            Ok(config) => config,
-            Err(error) => {
-                return Err(YamlFileError::with_message(format!(
-                    "failed to deserialize yaml config: {}",
-                    error
-                ))
-                .into())
-            }
-        };
-        Ok(cfg)
+        from_str(data, format)
+    }
+    fn to_string_as(&self, format: FileFormat) -> Result<String, Error>
+    where
+        Self: Serialize,
+    {
+        to_string(self, format)
+    }
+    fn from_yaml(data: String) -> Result<Self, Error>
+    where
+        Self: DeserializeOwned,
+    {
+        Self::from_str_as(&data, FileFormat::Yaml)
     }
     fn to_yaml(&self) -> Result<String, Error>
     where
         Self: Serialize,
     {
-        match serde_yaml::to_string(&self) {
-            Ok(val) => Ok(val),
-            Err(e) => Err(YamlFileError::with_message(format!(
-                "failed to encode key to yaml: {}",
-                e
-            ))
-            .into()),
-        }
+        self.to_string_as(FileFormat::Yaml)
     }
 
     fn default() -> Result<Self, Error>
     where
         Self: DeserializeOwned;
 
+    /// see [`load`]
     fn import(filename: &str) -> Result<Self, Error>
     where
         Self: DeserializeOwned,
-        Self: Clone,
-        Self: PartialEq,
     {
-        let filename = absolute_path(filename);
-        match fs::read_to_string(filename.as_str()) {
-            Ok(yaml) => YamlFile::from_yaml(yaml),
-            Err(error) => {
-                return Err(YamlFileError::with_message(format!(
-                    "faled to read file {}\n\t{}",
-                    filename, error
-                ))
-                .into())
-            }
-        }
+        load(filename)
     }
 
+    /// writes the file in the format of [`FileFormat::for_path`]
     fn export(&self, filename: &str) -> Result<String, Error>
     where
         Self: Serialize,
     {
-        let filename = absolute_path(filename);
-
-        let yaml = match self.to_yaml() {
-            Ok(val) => val,
-            Err(error) => return Err(error),
-        };
-        let mut file = match open_write(filename.as_str()) {
-            Ok(file) => file,
-            Err(error) => {
-                return Err(YamlFileError::with_message(format!(
-                    "failed to create file {}\n\t{}",
-                    filename, error
-                ))
-                .into())
-            }
-        };
-        file.write(yaml.as_ref()).unwrap();
-        Ok(filename)
+        save(self, filename, FileFormat::for_path(filename))
+    }
+
+    fn export_as(&self, filename: &str, format: FileFormat) -> Result<String, Error>
+    where
+        Self: Serialize,
+    {
+        save(self, filename, format)
+    }
+}
+
+/// former name of [`ConfigFile`] when configs were yaml only
+pub use ConfigFile as YamlFile;
+
+#[cfg(test)]
+mod serial_tests {
+    use crate::config::Config;
+    use crate::emit::TempEmission;
+    use crate::errors::Error;
+    use crate::serial::{ConfigFile, FileFormat};
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_config_formats() -> Result<(), Error> {
+        let mut config = Config::default()?;
+        config.add_path("/srv/projects", config.get_default().clone());
+        for format in [FileFormat::Yaml, FileFormat::Json, FileFormat::Toml] {
+            let text = config.to_string_as(format)?;
+            assert_equal!(FileFormat::sniff(&text), format);
+            assert_equal!(Config::from_str_as(&text, format)?, config);
+        }
+        assert_equal!(FileFormat::from_extension("obg/config.TOML"), Some(FileFormat::Toml));
+        assert_equal!(FileFormat::from_extension("key.kgz"), None);
+        for format in [FileFormat::Yaml, FileFormat::Json, FileFormat::Toml] {
+            assert_equal!(FileFormat::from_extension(&format!("share.1.{}", format.extension())), Some(format));
+        }
+
+        let (_, path) = TempEmission::now().papobg_8473776564_file()?;
+        let path = format!("{}", path.display());
+        config.export(&path)?;
+        config.export_as(&path, FileFormat::Toml)?;
+        assert_equal!(FileFormat::for_path(&path), FileFormat::Toml);
+        assert_equal!(Config::import(&path)?, config);
+        Ok(())
     }
 }
@@ -0,0 +1,274 @@
+/*!
+Shamir's Secret Sharing over GF(2^8)
+
+Splits the material of an [`Aes256Key`] (key, iv and blob) into `n`
+shares such that any `k` of them reconstruct the original key.
+*/
+use crate::aescbc::cdc::Aes256Key;
+use crate::aescbc::tp::{B128, B256};
+use crate::errors::Error;
+use crate::ioutils::{open_write, read_bytes};
+use crate::serial::{self, FileFormat};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b > 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 == a^-1 in GF(2^8)
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// splits `secret` into `shares` points of which `threshold` are
+/// required to recover it
+pub fn split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+    if threshold < 2 {
+        return Err(Error::SecretSharingError(format!(
+            "threshold must be at least 2, got {}",
+            threshold
+        )));
+    }
+    if shares < threshold {
+        return Err(Error::SecretSharingError(format!(
+            "cannot make {} shares with a threshold of {}",
+            shares, threshold
+        )));
+    }
+    let mut coefficients = vec![0u8; (threshold as usize - 1) * secret.len()];
+    OsRng.fill_bytes(&mut coefficients);
+
+    let mut result = Vec::with_capacity(shares as usize);
+    for x in 1..=shares {
+        let mut y = Vec::with_capacity(secret.len());
+        for (pos, byte) in secret.iter().enumerate() {
+            // horner's method from the highest degree coefficient
+            let mut acc = 0u8;
+            for degree in (0..threshold as usize - 1).rev() {
+                acc = gf_mul(acc, x) ^ coefficients[degree * secret.len() + pos];
+            }
+            y.push(gf_mul(acc, x) ^ byte);
+        }
+        result.push((x, y));
+    }
+    Ok(result)
+}
+
+/// recovers the secret from at least `threshold` points via lagrange
+/// interpolation at zero
+pub fn combine(points: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, Error> {
+    if points.is_empty() {
+        return Err(Error::SecretSharingError("no shares given".to_string()));
+    }
+    let length = points[0].1.len();
+    for (pos, (x, y)) in points.iter().enumerate() {
+        if *x == 0 {
+            return Err(Error::SecretSharingError("share index cannot be 0".to_string()));
+        }
+        if y.len() != length {
+            return Err(Error::SecretSharingError(format!(
+                "share {} is {} bytes long instead of {}",
+                x,
+                y.len(),
+                length
+            )));
+        }
+        if points[..pos].iter().any(|(other, _)| other == x) {
+            return Err(Error::SecretSharingError(format!("duplicate share index {}", x)));
+        }
+    }
+    let mut secret = vec![0u8; length];
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, (xj, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*xj, gf_inv(xi ^ xj)));
+            }
+        }
+        for (pos, byte) in yi.iter().enumerate() {
+            secret[pos] ^= gf_mul(*byte, basis);
+        }
+    }
+    Ok(secret)
+}
+
+/// a single share of a key as persisted to disk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyShare {
+    pub version: String,
+    pub index: u8,
+    pub threshold: u8,
+    pub shares: u8,
+    pub fingerprint: String,
+    pub cycles: Option<u64>,
+    pub name: Option<String>,
+    pub share: String,
+}
+
+impl KeyShare {
+    pub fn save_to_yaml_file(&self, filename: String) -> Result<(), Error> {
+        let mut file = open_write(&filename)?;
+        let yaml = serde_yaml::to_string(self)?;
+        Ok(file.write_all(yaml.as_bytes())?)
+    }
+    pub fn load_from_yaml_file(filename: String) -> Result<KeyShare, Error> {
+        let bytes = read_bytes(&filename)?;
+        let share: KeyShare = serde_yaml::from_slice(&bytes)?;
+        Ok(share)
+    }
+    /// writes the share as yaml, json or toml
+    pub fn save_to_config_file(&self, filename: String, format: FileFormat) -> Result<(), Error> {
+        serial::save(self, &filename, format)?;
+        Ok(())
+    }
+    /// reads a yaml, json or toml share, see [`serial::load`]
+    pub fn load_from_config_file(filename: String) -> Result<KeyShare, Error> {
+        serial::load(&filename)
+    }
+}
+
+/// splits the key, iv and blob of `key` into `shares` shares
+pub fn split_key(key: &Aes256Key, threshold: u8, shares: u8) -> Result<Vec<KeyShare>, Error> {
+    let mut secret = Vec::<u8>::new();
+    secret.extend(key.skey()?);
+    secret.extend(key.siv()?);
+    secret.extend(key.sblob());
+    let fingerprint = key.fingerprint()?;
+    Ok(split(&secret, threshold, shares)?
+        .into_iter()
+        .map(|(index, share)| KeyShare {
+            version: key.version.clone(),
+            index,
+            threshold,
+            shares,
+            fingerprint: fingerprint.clone(),
+            cycles: key.cycles,
+            name: key.name(),
+            share: hex::encode(share),
+        })
+        .collect())
+}
+
+/// reconstructs the key out of `shares` and verifies its fingerprint
+pub fn combine_key(shares: &[KeyShare]) -> Result<Aes256Key, Error> {
+    let first = match shares.first() {
+        Some(share) => share,
+        None => return Err(Error::SecretSharingError("no shares given".to_string())),
+    };
+    if shares.iter().any(|share| share.fingerprint != first.fingerprint) {
+        return Err(Error::SecretSharingError(
+            "shares belong to different keys".to_string(),
+        ));
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(Error::SecretSharingError(format!(
+            "{} shares given but {} are required",
+            shares.len(),
+            first.threshold
+        )));
+    }
+    let mut points = Vec::with_capacity(shares.len());
+    for share in shares {
+        points.push((share.index, hex::decode(&share.share)?));
+    }
+    let secret = combine(&points)?;
+    if secret.len() < 48 {
+        return Err(Error::SecretSharingError(format!(
+            "combined secret is too short: {} bytes",
+            secret.len()
+        )));
+    }
+    let mut skey: B256 = [0; 32];
+    skey.copy_from_slice(&secret[..32]);
+    let mut siv: B128 = [0; 16];
+    siv.copy_from_slice(&secret[32..48]);
+    let key = Aes256Key::new(skey, siv, &secret[48..], first.cycles.unwrap_or(0));
+    let key = match &first.name {
+        Some(name) => key.with_name(name.clone())?,
+        None => key,
+    };
+    if key.fingerprint()? != first.fingerprint {
+        return Err(Error::SecretSharingError(format!(
+            "fingerprint mismatch: expected {} got {}",
+            first.fingerprint,
+            key.fingerprint()?
+        )));
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod shamir_tests {
+    use crate::aescbc::cdc::Aes256Key;
+    use crate::emit::TempEmission;
+    use crate::errors::Error;
+    use crate::serial::FileFormat;
+    use crate::shamir::{combine, combine_key, split, split_key, KeyShare};
+    use k9::assert_equal;
+
+    #[test]
+    pub fn test_split_combine_any_threshold_subset() -> Result<(), Error> {
+        let secret = b"seemingly random bytes".to_vec();
+        let shares = split(&secret, 3, 5)?;
+        assert_equal!(shares.len(), 5);
+
+        assert_equal!(combine(&shares[..3])?, secret);
+        assert_equal!(combine(&shares[2..])?, secret);
+        assert_equal!(
+            combine(&[shares[4].clone(), shares[0].clone(), shares[2].clone()])?,
+            secret
+        );
+        assert!(combine(&shares[..2])? != secret);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_split_combine_key() -> Result<(), Error> {
+        let key = Aes256Key::new([0x47; 32], [0x54; 16], &[0x37; 237], 0x35)
+            .with_name("break-glass.kgz".to_string())?;
+        let shares = split_key(&key, 2, 3)?;
+        assert_equal!(shares[1].fingerprint, key.fingerprint()?);
+
+        let combined = combine_key(&shares[1..])?;
+        assert_equal!(combined, key);
+
+        let mut tampered = shares.clone();
+        tampered[0].share = tampered[1].share.clone();
+        tampered[0].index = 3;
+        assert!(combine_key(&tampered[..2]).is_err());
+        assert!(combine_key(&shares[..1]).is_err());
+
+        let (_, path) = TempEmission::now().papobg_8473776564_file()?;
+        let path = format!("{}", path.display());
+        for format in [FileFormat::Yaml, FileFormat::Json, FileFormat::Toml] {
+            shares[2].save_to_config_file(path.clone(), format)?;
+            assert_equal!(KeyShare::load_from_config_file(path.clone())?, shares[2]);
+        }
+        Ok(())
+    }
+}
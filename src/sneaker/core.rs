@@ -13,6 +13,28 @@ pub fn magic_id() -> Vec<u8> {
     ]
 }
 
+/// magic of files whose key is wrapped to x25519 recipients
+pub fn envelope_id() -> Vec<u8> {
+    let mut id = magic_id();
+    id[7] = 0x01;
+    id
+}
+
+/// magic of files whose key is derived from a passphrase
+pub fn passphrase_id() -> Vec<u8> {
+    let mut id = magic_id();
+    id[7] = 0x02;
+    id
+}
+
+/// magic of files sealed with a key file, followed by the check value
+/// of the key and a payload padded to whole blocks
+pub fn checked_id() -> Vec<u8> {
+    let mut id = magic_id();
+    id[7] = 0x03;
+    id
+}
+
 #[cfg(test)]
 mod sneaker_tests {
 
@@ -29,6 +51,19 @@ mod sneaker_tests {
         assert_equal!(zid[5], 0x03);
         assert_equal!(zid[4], 0x01);
 
+        let eid = core::envelope_id();
+        assert_equal!(eid.len(), core::MAGIC_WIDTH);
+        assert_equal!(eid[..4].to_vec(), pfx);
+        assert!(eid != zid);
+
+        let pid = core::passphrase_id();
+        assert_equal!(pid.len(), core::MAGIC_WIDTH);
+        assert!(pid != zid && pid != eid);
+
+        let cid = core::checked_id();
+        assert_equal!(cid.len(), core::MAGIC_WIDTH);
+        assert!(cid != zid && cid != eid && cid != pid);
+
         assert_equal!(pfx.len(), 4);
         assert_equal!(pfx, vec![0x00, 0x00, 0x02, 0x00]);
     }
@@ -2,20 +2,32 @@ use crate::errors::Error;
 use crate::sneaker::core;
 use std::io::{Read, Seek};
 
-pub fn xstack<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
-    let mut start: Vec<u8> = Vec::new();
-    start.resize(core::STACK_WIDTH, 0x4);
+/// reads up to `width` bytes from the start of `source`, fewer when it is shorter
+pub fn read_start<S: Read + Seek>(source: &mut S, width: usize) -> Result<Vec<u8>, Error> {
+    let mut start: Vec<u8> = Vec::with_capacity(width);
     source.rewind()?;
-    source.read_exact(&mut start)?;
-    Ok(start == core::stack())
+    source.by_ref().take(width as u64).read_to_end(&mut start)?;
+    Ok(start)
+}
+
+pub fn xstack<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
+    Ok(read_start(source, core::STACK_WIDTH)? == core::stack())
 }
 
 pub fn is_snuck<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
-    let mut start: Vec<u8> = Vec::new();
-    start.resize(core::MAGIC_WIDTH, 0x37);
-    source.rewind()?;
-    source.read_exact(&mut start)?;
-    Ok(start == core::magic_id())
+    Ok(read_start(source, core::MAGIC_WIDTH)? == core::magic_id())
+}
+
+pub fn is_checked<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
+    Ok(read_start(source, core::MAGIC_WIDTH)? == core::checked_id())
+}
+
+pub fn is_enveloped<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
+    Ok(read_start(source, core::MAGIC_WIDTH)? == core::envelope_id())
+}
+
+pub fn is_passphrased<S: Read + Seek>(source: &mut S) -> Result<bool, Error> {
+    Ok(read_start(source, core::MAGIC_WIDTH)? == core::passphrase_id())
 }
 
 #[cfg(test)]
@@ -47,4 +59,11 @@ mod sneaker_tests {
 
         assert_equal!(result, true);
     }
+    #[test]
+    pub fn test_io_short_source() {
+        let mut buf = Cursor::new(vec![0x00, 0x00, 0x02]);
+        assert_equal!(io::xstack(&mut buf).unwrap(), false);
+        assert_equal!(io::is_snuck(&mut buf).unwrap(), false);
+        assert_equal!(io::is_enveloped(&mut Cursor::new(Vec::new())).unwrap(), false);
+    }
 }
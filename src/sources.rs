@@ -0,0 +1,269 @@
+/*!
+Password and salt sources
+
+Each `--password` or `--salt` may be prefixed with a scheme which
+states explicitly where its bytes come from:
+
+- `file:path` or `file:///absolute/path`
+- `env:VARIABLE`
+- `fd:3` an inherited file descriptor
+- `stdin:`
+- `literal:text`
+
+Secrets typed at a prompt are carried as [`SecretSource::Prompt`] and
+never pass through a string, `parse` never returns one.
+
+Referenced sources which are missing or empty are errors. Values
+without a known scheme keep the historical behaviour: the file if
+the path exists, otherwise the value itself, which the CLI warns
+about since a mistyped path silently becomes the secret.
+
+Descriptors are borrowed rather than owned: obg checks that they are
+open for reading and never closes them.
+*/
+use crate::aescbc::SecretBytes;
+use crate::errors::Error;
+use crate::ioutils::{absolute_path, read_bytes_high_water_mark};
+use std::fs::File;
+use std::io::Read;
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+use std::path::Path;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    File(String),
+    Env(String),
+    Fd(i32),
+    Stdin,
+    Literal(String),
+    Legacy(String),
+    Prompt(SecretBytes),
+}
+
+fn scheme_payload<'a>(src: &'a str, scheme: &str) -> &'a str {
+    &src[scheme.len() + 1..]
+}
+
+impl SecretSource {
+    pub fn parse(src: &str) -> Result<SecretSource, Error> {
+        let url = match Url::parse(src) {
+            Ok(url) => url,
+            Err(_) => return Ok(SecretSource::Legacy(src.to_string())),
+        };
+        let payload = scheme_payload(src, url.scheme());
+        match url.scheme() {
+            "file" => {
+                if payload.starts_with("//") {
+                    match url.to_file_path() {
+                        Ok(path) => Ok(SecretSource::File(format!("{}", path.display()))),
+                        Err(_) => Err(Error::InvalidSource(format!("invalid file uri {}", src))),
+                    }
+                } else if payload.is_empty() {
+                    Err(Error::InvalidSource("file: requires a path".to_string()))
+                } else {
+                    Ok(SecretSource::File(absolute_path(payload)))
+                }
+            }
+            "env" => {
+                if payload.is_empty() {
+                    Err(Error::InvalidSource("env: requires a variable name".to_string()))
+                } else {
+                    Ok(SecretSource::Env(payload.to_string()))
+                }
+            }
+            "fd" => match payload.parse::<i32>() {
+                Ok(fd) if fd >= 0 => Ok(SecretSource::Fd(fd)),
+                _ => Err(Error::InvalidSource(format!("invalid file descriptor {:?}", payload))),
+            },
+            "stdin" => {
+                if payload.is_empty() || payload == "-" {
+                    Ok(SecretSource::Stdin)
+                } else {
+                    Err(Error::InvalidSource(format!("unexpected {:?} after stdin:", payload)))
+                }
+            }
+            "literal" => Ok(SecretSource::Literal(payload.to_string())),
+            _ => Ok(SecretSource::Legacy(src.to_string())),
+        }
+    }
+    pub fn parse_all(srcs: &[String]) -> Result<Vec<SecretSource>, Error> {
+        srcs.iter().map(|src| SecretSource::parse(src)).collect()
+    }
+
+    /// short name of the kind of source which never includes the secret
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SecretSource::File(_) => "file",
+            SecretSource::Env(_) => "env",
+            SecretSource::Fd(_) => "fd",
+            SecretSource::Stdin => "stdin",
+            SecretSource::Literal(_) => "literal",
+            SecretSource::Prompt(_) => "prompt",
+            SecretSource::Legacy(path) => {
+                if Path::new(path).exists() {
+                    "file"
+                } else {
+                    "literal"
+                }
+            }
+        }
+    }
+
+    /// describes how a value without a scheme is read, without ever
+    /// including the value, `None` for values with a scheme
+    pub fn ambiguity(&self) -> Option<String> {
+        match self {
+            SecretSource::Legacy(_) => Some(format!(
+                "has no scheme and is read as {} since {}, prefix it with file: or literal:",
+                match self.kind() {
+                    "file" => "a file",
+                    _ => "a literal",
+                },
+                match self.kind() {
+                    "file" => "such a path exists",
+                    _ => "no such path exists",
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    /// reads at most `hwm` bytes out of files, descriptors and stdin
+    pub fn read(&self, hwm: u64) -> Result<Vec<u8>, Error> {
+        let bytes = match self {
+            SecretSource::File(path) => {
+                if !Path::new(path).exists() {
+                    return Err(Error::InvalidSource(format!("file {} does not exist", path)));
+                }
+                read_bytes_high_water_mark(path, hwm)?
+            }
+            SecretSource::Env(name) => match std::env::var_os(name) {
+                Some(value) => value.to_string_lossy().as_bytes().to_vec(),
+                None => {
+                    return Err(Error::InvalidSource(format!(
+                        "environment variable {} is not set",
+                        name
+                    )))
+                }
+            },
+            SecretSource::Fd(fd) => {
+                check_readable_fd(*fd)?;
+                // SAFETY: the descriptor was just found open and the
+                // ManuallyDrop keeps it from being closed, such that its
+                // owner, possibly another source, may still use it
+                let file = ManuallyDrop::new(unsafe { File::from_raw_fd(*fd) });
+                let mut bytes = Vec::new();
+                (&*file).take(hwm).read_to_end(&mut bytes)?;
+                bytes
+            }
+            SecretSource::Stdin => {
+                let mut bytes = Vec::new();
+                std::io::stdin().take(hwm).read_to_end(&mut bytes)?;
+                bytes
+            }
+            SecretSource::Literal(value) => value.as_bytes().to_vec(),
+            SecretSource::Prompt(secret) => secret.expose().to_vec(),
+            SecretSource::Legacy(value) => {
+                if Path::new(value).exists() {
+                    read_bytes_high_water_mark(value, hwm)?
+                } else {
+                    value.as_bytes().to_vec()
+                }
+            }
+        };
+        if bytes.is_empty() {
+            return Err(Error::InvalidSource(format!("{} source is empty", self.kind())));
+        }
+        Ok(bytes)
+    }
+}
+
+/// fails unless `fd` is an open descriptor which may be read from
+fn check_readable_fd(fd: i32) -> Result<(), Error> {
+    // SAFETY: F_GETFL neither dereferences memory nor alters the descriptor
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(Error::InvalidSource(format!("file descriptor {} is not open", fd)));
+    }
+    if flags & libc::O_ACCMODE == libc::O_WRONLY {
+        return Err(Error::InvalidSource(format!("file descriptor {} is not open for reading", fd)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod sources_tests {
+    use crate::errors::Error;
+    use crate::ioutils::absolute_path;
+    use crate::sources::SecretSource;
+    use k9::assert_equal;
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    pub fn test_parse_schemes() -> Result<(), Error> {
+        assert_equal!(
+            SecretSource::parse("file:///etc/hostname")?,
+            SecretSource::File("/etc/hostname".to_string())
+        );
+        assert_equal!(
+            SecretSource::parse("file:tests/key.png")?,
+            SecretSource::File(absolute_path("tests/key.png"))
+        );
+        assert_equal!(
+            SecretSource::parse("env:OBG_SECRET")?,
+            SecretSource::Env("OBG_SECRET".to_string())
+        );
+        assert_equal!(SecretSource::parse("fd:3")?, SecretSource::Fd(3));
+        assert_equal!(SecretSource::parse("stdin:")?, SecretSource::Stdin);
+        assert_equal!(
+            SecretSource::parse("literal:with spaces: and colons")?,
+            SecretSource::Literal("with spaces: and colons".to_string())
+        );
+        assert_equal!(
+            SecretSource::parse("https://soundcloud.com/wave-mandala/home-of-the-future")?,
+            SecretSource::Legacy("https://soundcloud.com/wave-mandala/home-of-the-future".to_string())
+        );
+        assert_equal!(
+            SecretSource::parse("slytherin")?,
+            SecretSource::Legacy("slytherin".to_string())
+        );
+        assert!(SecretSource::parse("fd:three").is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_read_fails_loudly() -> Result<(), Error> {
+        assert!(SecretSource::parse("file:tests/kye.png")?.read(1024).is_err());
+        assert!(SecretSource::parse("env:OBG_TEST_SURELY_UNSET")?.read(1024).is_err());
+        assert!(SecretSource::parse("literal:")?.read(1024).is_err());
+        assert_equal!(SecretSource::parse("file:tests/key.png")?.read(8)?.len(), 8);
+        assert_equal!(
+            SecretSource::parse("tests/kye.png")?.read(1024)?,
+            b"tests/kye.png".to_vec()
+        );
+        assert!(SecretSource::parse("fd:987654")?.read(1024).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_fd_is_borrowed() -> Result<(), Error> {
+        let file = std::fs::File::open("tests/key.png")?;
+        let source = SecretSource::parse(&format!("fd:{}", file.as_raw_fd()))?;
+        assert_equal!(source.read(8)?.len(), 8);
+        assert_equal!(source.read(8)?.len(), 8);
+        assert!(file.metadata()?.len() > 16);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_ambiguity() -> Result<(), Error> {
+        assert_equal!(SecretSource::parse("literal:slytherin")?.ambiguity(), None);
+        let ambiguity = SecretSource::parse("slytherin")?.ambiguity().unwrap();
+        assert!(ambiguity.contains("as a literal"));
+        assert!(!ambiguity.contains("slytherin"));
+        assert!(SecretSource::parse("tests/key.png")?.ambiguity().unwrap().contains("as a file"));
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+/*!
+Library Facade
+
+[`Vault`] encrypts and decrypts with an [`Aes256Key`] the same way the
+`encrypt`/`decrypt` subcommands do, so files written by either one are
+readable by the other. [`KeyBuilder`] derives or generates such keys with
+the defaults of `obg keygen`.
+
+```
+use obg::{KeyBuilder, Vault};
+
+let key = KeyBuilder::new()
+    .password(b"correct horse")
+    .salt(b"battery staple")
+    .cycles(1)
+    .build()?;
+let vault = Vault::new(key);
+let ciphertext = vault.encrypt_bytes(b"hello")?;
+assert_eq!(vault.decrypt_bytes(&ciphertext)?, b"hello");
+# Ok::<(), obg::errors::Error>(())
+```
+*/
+use crate::aescbc::{Aes256Key, DerivationScheme, Pbkdf2Config};
+use crate::clap::{DEFAULT_CYCLES, DEFAULT_DERIVATION_SCHEME};
+use crate::errors::{DecryptionError, Error};
+use crate::pap::{self, Transcoded};
+use crate::progress::Silent;
+use crate::sneaker;
+use crate::sources::SecretSource;
+use std::fmt;
+use std::io::{Cursor, Read, Write};
+use zeroize::Zeroizing;
+
+/// high-water mark of secrets read by [`KeyBuilder::password_source`]
+/// and [`KeyBuilder::salt_source`], same as the `keygen` default
+pub const SOURCE_HWM: u64 = 0x400;
+
+/// encrypts and decrypts with a single key
+#[derive(Debug, Clone)]
+pub struct Vault {
+    key: Aes256Key,
+}
+
+impl Vault {
+    pub fn new(key: Aes256Key) -> Vault {
+        Vault { key }
+    }
+    /// loads a yaml, json, toml or binary key file as `--key-file` does
+    pub fn from_key_file(path: &str) -> Result<Vault, Error> {
+        Ok(Vault::new(Aes256Key::load(path.to_string())?))
+    }
+    pub fn key(&self) -> &Aes256Key {
+        &self.key
+    }
+    pub fn fingerprint(&self) -> Result<String, Error> {
+        self.key.fingerprint()
+    }
+
+    /// the contents `obg encrypt file` would write for `plaintext`
+    pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut source = Cursor::new(plaintext);
+        if sneaker::io::is_snuck(&mut source)? || sneaker::io::is_checked(&mut source)? {
+            return Err(Error::AlreadyEncrypted("plaintext is already encrypted".to_string()));
+        }
+        let mut ciphertext = sneaker::core::checked_id();
+        ciphertext.extend(pap::check_value(pap::CHECK_CONTEXT, &self.key)?);
+        let payload = pap::seal_padded(&self.key, plaintext)?;
+        let tag = pap::tag(&self.key, &ciphertext, &payload)?;
+        ciphertext.extend(payload);
+        ciphertext.extend(tag);
+        Ok(ciphertext)
+    }
+    /// reverses [`Vault::encrypt_bytes`] after checking its tag, also
+    /// decrypting the files sealed before obg 4.1 which carry no tag
+    pub fn decrypt_bytes(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut source = Cursor::new(ciphertext);
+        if sneaker::io::is_snuck(&mut source)? {
+            return pap::unseal(&self.key, &ciphertext[sneaker::core::MAGIC_WIDTH..]);
+        }
+        if !sneaker::io::is_checked(&mut source)? {
+            return Err(Error::NotEncrypted(format!(
+                "ciphertext does not appear to be encrypted with {} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )));
+        }
+        let header_width = sneaker::core::MAGIC_WIDTH + 16;
+        if ciphertext.len() < header_width + pap::TAG_WIDTH {
+            return Err(DecryptionError::new("ciphertext is truncated".to_string()).into());
+        }
+        let (header, payload) = ciphertext.split_at(header_width);
+        if header[sneaker::core::MAGIC_WIDTH..] != pap::check_value(pap::CHECK_CONTEXT, &self.key)? {
+            return Err(DecryptionError::new("ciphertext was encrypted with another key".to_string()).into());
+        }
+        let (payload, tag) = payload.split_at(payload.len() - pap::TAG_WIDTH);
+        pap::check_tag(&self.key, header, payload, tag)?;
+        pap::unseal_padded(&self.key, payload)
+    }
+
+    /// encrypts everything read from `reader` into `writer`
+    pub fn encrypt_reader<R: Read, W: Write>(&self, mut reader: R, mut writer: W) -> Result<Transcoded, Error> {
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        let ciphertext = self.encrypt_bytes(&plaintext)?;
+        writer.write_all(&ciphertext)?;
+        Ok(Transcoded {
+            read: plaintext.len() as u64,
+            written: ciphertext.len() as u64,
+        })
+    }
+    /// decrypts everything read from `reader` into `writer`
+    pub fn decrypt_reader<R: Read, W: Write>(&self, mut reader: R, mut writer: W) -> Result<Transcoded, Error> {
+        let mut ciphertext = Vec::new();
+        reader.read_to_end(&mut ciphertext)?;
+        let plaintext = self.decrypt_bytes(&ciphertext)?;
+        writer.write_all(&plaintext)?;
+        Ok(Transcoded {
+            read: ciphertext.len() as u64,
+            written: plaintext.len() as u64,
+        })
+    }
+
+    pub fn encrypt_file(&self, input_file: &str, output_file: &str) -> Result<Transcoded, Error> {
+        pap::encrypt_file(self.key.clone(), input_file.to_string(), output_file.to_string(), &Silent)
+    }
+    pub fn decrypt_file(&self, input_file: &str, output_file: &str) -> Result<Transcoded, Error> {
+        pap::decrypt_file(self.key.clone(), input_file.to_string(), output_file.to_string(), &Silent)
+    }
+}
+
+/// derives a key from a password and a salt, or generates a random one,
+/// with the same defaults as `obg keygen`
+#[derive(Clone)]
+pub struct KeyBuilder {
+    password: Zeroizing<Vec<u8>>,
+    salt: Zeroizing<Vec<u8>>,
+    cycles: u64,
+    scheme: DerivationScheme,
+    shuffle_iv: bool,
+    deterministic: bool,
+    blob_length: Option<u64>,
+    name: Option<String>,
+    random: bool,
+}
+
+impl Default for KeyBuilder {
+    fn default() -> KeyBuilder {
+        KeyBuilder {
+            password: Zeroizing::new(Vec::new()),
+            salt: Zeroizing::new(Vec::new()),
+            cycles: DEFAULT_CYCLES,
+            scheme: DEFAULT_DERIVATION_SCHEME,
+            shuffle_iv: false,
+            deterministic: false,
+            blob_length: None,
+            name: None,
+            random: false,
+        }
+    }
+}
+
+impl fmt::Debug for KeyBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyBuilder")
+            .field("password", &format_args!("<{} bytes redacted>", self.password.len()))
+            .field("salt", &format_args!("<{} bytes redacted>", self.salt.len()))
+            .field("cycles", &self.cycles)
+            .field("scheme", &self.scheme)
+            .field("shuffle_iv", &self.shuffle_iv)
+            .field("deterministic", &self.deterministic)
+            .field("blob_length", &self.blob_length)
+            .field("name", &self.name)
+            .field("random", &self.random)
+            .finish()
+    }
+}
+
+/// appends `bytes` to `secret` without leaving a copy of it behind in
+/// memory freed by growing the vector
+fn append(secret: &mut Zeroizing<Vec<u8>>, bytes: &[u8]) {
+    let mut joined = Zeroizing::new(Vec::with_capacity(secret.len() + bytes.len()));
+    joined.extend_from_slice(secret);
+    joined.extend_from_slice(bytes);
+    *secret = joined;
+}
+
+impl KeyBuilder {
+    pub fn new() -> KeyBuilder {
+        KeyBuilder::default()
+    }
+    /// appends to the password, as repeated `--password` options do
+    pub fn password(mut self, password: &[u8]) -> KeyBuilder {
+        append(&mut self.password, password);
+        self
+    }
+    /// appends a secret source such as `env:NAME` or `file:path` to the password
+    pub fn password_source(mut self, source: &str) -> Result<KeyBuilder, Error> {
+        let password = Zeroizing::new(SecretSource::parse(source)?.read(SOURCE_HWM)?);
+        append(&mut self.password, &password);
+        Ok(self)
+    }
+    /// appends to the salt, as repeated `--salt` options do
+    pub fn salt(mut self, salt: &[u8]) -> KeyBuilder {
+        append(&mut self.salt, salt);
+        self
+    }
+    /// appends a secret source such as `env:NAME` or `file:path` to the salt
+    pub fn salt_source(mut self, source: &str) -> Result<KeyBuilder, Error> {
+        let salt = Zeroizing::new(SecretSource::parse(source)?.read(SOURCE_HWM)?);
+        append(&mut self.salt, &salt);
+        Ok(self)
+    }
+    pub fn cycles(mut self, cycles: u64) -> KeyBuilder {
+        self.cycles = cycles;
+        self
+    }
+    pub fn scheme(mut self, scheme: DerivationScheme) -> KeyBuilder {
+        self.scheme = scheme;
+        self
+    }
+    pub fn shuffle_iv(mut self, shuffle_iv: bool) -> KeyBuilder {
+        self.shuffle_iv = shuffle_iv;
+        self
+    }
+    /// derives the blob length and any shuffling of the iv from the
+    /// password and salt, as `keygen --deterministic` does
+    pub fn deterministic(mut self, deterministic: bool) -> KeyBuilder {
+        self.deterministic = deterministic;
+        self
+    }
+    pub fn blob_length(mut self, blob_length: u64) -> KeyBuilder {
+        self.blob_length = Some(blob_length);
+        self
+    }
+    /// names the key, usually after the file it is saved to
+    pub fn name(mut self, name: &str) -> KeyBuilder {
+        self.name = Some(name.to_string());
+        self
+    }
+    /// generates a random key, ignoring password and salt
+    pub fn random(mut self) -> KeyBuilder {
+        self.random = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Aes256Key, Error> {
+        let key = if self.random {
+            Aes256Key::random(self.blob_length)?
+        } else {
+            if self.password.is_empty() {
+                return Err(Error::EmptyPassword("provide a password or build a random key".to_string()));
+            }
+            if self.salt.is_empty() {
+                return Err(Error::EmptySalt("provide a salt or build a random key".to_string()));
+            }
+            Aes256Key::derive_from_bytes(
+                &self.password,
+                &self.salt,
+                &Pbkdf2Config::new(self.scheme, self.cycles, self.shuffle_iv, self.deterministic),
+                self.blob_length,
+            )?
+        };
+        match self.name {
+            Some(name) => key.with_name(name),
+            None => Ok(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod vault_tests {
+    use crate::emit::TempEmission;
+    use crate::errors::Error;
+    use crate::pap;
+    use crate::progress::Silent;
+    use crate::sneaker;
+    use crate::vault::{KeyBuilder, Vault};
+    use k9::assert_equal;
+    use std::io::Write;
+
+    fn vault() -> Result<Vault, Error> {
+        Ok(Vault::new(KeyBuilder::new().password(b"vault").salt(b"tests").cycles(3).build()?))
+    }
+
+    #[test]
+    pub fn test_vault_bytes() -> Result<(), Error> {
+        let vault = vault()?;
+        let ciphertext = vault.encrypt_bytes(b"plaintext")?;
+        assert_equal!(vault.decrypt_bytes(&ciphertext)?, b"plaintext".to_vec());
+        assert!(vault.encrypt_bytes(&ciphertext).is_err());
+        assert!(vault.decrypt_bytes(b"plaintext").is_err());
+        for position in [sneaker::core::MAGIC_WIDTH + 16, ciphertext.len() - 1] {
+            let mut tampered = ciphertext.clone();
+            tampered[position] ^= 0x01;
+            assert!(matches!(vault.decrypt_bytes(&tampered), Err(Error::DecryptionError(_))));
+        }
+        assert!(vault.decrypt_bytes(&ciphertext[..sneaker::core::MAGIC_WIDTH + 16]).is_err());
+
+        let mut plaintext = Vec::new();
+        let transcoded = vault.decrypt_reader(ciphertext.as_slice(), &mut plaintext)?;
+        assert_equal!(plaintext, b"plaintext".to_vec());
+        assert_equal!(transcoded.read, ciphertext.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_vault_matches_files() -> Result<(), Error> {
+        let vault = vault()?;
+        let (mut file, path) = TempEmission::now().papobg_8473776564_file()?;
+        let path = format!("{}", path.display());
+        file.write_all(&vault.encrypt_bytes(b"same format")?)?;
+        let (txtpath, obgpath) = (format!("{}.txt", path), format!("{}.obg", path));
+        pap::decrypt_file(vault.key().clone(), path, txtpath.clone(), &Silent)?;
+        assert_equal!(std::fs::read(&txtpath)?, b"same format".to_vec());
+
+        vault.encrypt_file(&txtpath, &obgpath)?;
+        assert_equal!(vault.decrypt_bytes(&std::fs::read(&obgpath)?)?, b"same format".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_key_builder() -> Result<(), Error> {
+        assert!(KeyBuilder::new().salt(b"salt").build().is_err());
+        assert!(KeyBuilder::new().password(b"password").build().is_err());
+
+        let split = KeyBuilder::new().password(b"pass").password(b"word").salt(b"salt").cycles(3).build()?;
+        let whole = KeyBuilder::new().password(b"password").salt(b"salt").cycles(3).build()?;
+        assert_equal!(split.skey()?, whole.skey()?);
+
+        let random = KeyBuilder::new().random().name("random.key").build()?;
+        assert!(random.skey()? != whole.skey()?);
+
+        let debug = format!("{:?}", KeyBuilder::new().password(b"hunter2").salt(b"pepper"));
+        assert!(!debug.contains("hunter2") && !debug.contains("104, 117"));
+        assert!(debug.contains("<7 bytes redacted>"));
+        Ok(())
+    }
+}